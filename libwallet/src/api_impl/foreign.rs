@@ -105,7 +105,6 @@ where
 	let keychain = w.keychain(keychain_mask)?;
 
 	//let mut ledger = LedgerDevice::new();
-	let mut keykeeper = LedgerKeyKeeper::new();
 
 	let context = tx::add_output_to_slate(
 		&mut *w,
@@ -119,7 +118,9 @@ where
 
 	// Add our contribution to the offset
 	if hardware {
-		keykeeper.sign_receiver(slate);
+		let mut keykeeper = LedgerKeyKeeper::new()?;
+		// TODO: add the nonce and partial signature from the device to the slate
+		let _response = keykeeper.sign_receiver(&keychain, &context, &ret_slate)?;
 	//ledger.sign_receiver(&keychain, &context);
 	} else {
 		ret_slate.adjust_offset(&keychain, &context)?;
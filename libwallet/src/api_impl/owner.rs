@@ -531,8 +531,12 @@ where
 			args.hardware,
 		)?
 	};
-	let mut keykeeper = LedgerKeyKeeper::new();
-	keykeeper.sign_sender(&slate, height);
+	if args.hardware {
+		let mut keykeeper = LedgerKeyKeeper::new()?;
+		let keychain = w.keychain(keychain_mask)?;
+		// TODO: add the nonce and partial signature from the device to the slate
+		let _response = keykeeper.sign_sender(&keychain, &context, &slate, height)?;
+	}
 
 	// Payment Proof, add addresses to slate and save address
 	// TODO: Note we only use single derivation path for now,
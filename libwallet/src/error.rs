@@ -19,6 +19,7 @@ use crate::grin_core::libtx;
 use crate::grin_keychain;
 use crate::grin_store;
 use crate::grin_util::secp;
+use crate::hw::ledger_error::{LedgerAppError, LedgerHIDError};
 use crate::util;
 use failure::{Backtrace, Context, Fail};
 use std::env;
@@ -306,6 +307,18 @@ pub enum ErrorKind {
 	#[fail(display = "Stored Tx error: {}", _0)]
 	StoredTx(String),
 
+	/// Hardware wallet error
+	#[fail(display = "Hardware wallet error: {}", _0)]
+	HardwareWallet(String),
+
+	/// No hardware wallet is connected
+	#[fail(display = "No hardware wallet found")]
+	HardwareNotFound,
+
+	/// Transaction declined by the user on the hardware wallet
+	#[fail(display = "Transaction rejected on device")]
+	HardwareUserRejected,
+
 	/// Other
 	#[fail(display = "Generic error: {}", _0)]
 	GenericError(String),
@@ -450,3 +463,27 @@ impl From<bech32::Error> for Error {
 		}
 	}
 }
+
+impl From<LedgerAppError> for Error {
+	fn from(error: LedgerAppError) -> Error {
+		let kind = match error {
+			LedgerAppError::UserRejected => ErrorKind::HardwareUserRejected,
+			e => ErrorKind::HardwareWallet(format!("{}", e)),
+		};
+		Error {
+			inner: Context::new(kind),
+		}
+	}
+}
+
+impl From<LedgerHIDError> for Error {
+	fn from(error: LedgerHIDError) -> Error {
+		let kind = match error {
+			LedgerHIDError::DeviceNotFound => ErrorKind::HardwareNotFound,
+			e => ErrorKind::HardwareWallet(format!("{}", e)),
+		};
+		Error {
+			inner: Context::new(kind),
+		}
+	}
+}
@@ -54,15 +54,33 @@ pub enum LedgerAppError {
 	/// Crypto related errors
 	#[error("Crypto")]
 	Crypto,
-	/// Utf8 related errors
-	#[error("Utf8 conversion error")]
-	Utf8,
+	/// Utf8 related errors, with the field name and the hex encoded bytes
+	#[error("Utf8 conversion error in {0}: 0x{1}")]
+	Utf8(String, String),
+	/// A numeric field couldn't be parsed, with the field name and its text
+	#[error("invalid number in {0}: {1:?}")]
+	InvalidNumber(String, String),
 	/// Format ID error
 	#[error("response format ID not recognized")]
 	InvalidFormatID,
 	/// HexEncode
 	#[error("Couldn't encode string to HEX")]
 	HexEncode,
+	/// The user declined the transaction on the device
+	#[error("transaction rejected on device")]
+	UserRejected,
+	/// The account id is empty, too long or has invalid characters
+	#[error("invalid account id: {0}")]
+	InvalidAccountId(String),
+	/// Transaction data couldn't be serialized for the device
+	#[error("serialization error: {0}")]
+	Serialization(String),
+	/// The device kept answering with heartbeats
+	#[error("no answer from device after {0} heartbeats")]
+	TooManyHeartbeats(usize),
+	/// Not enough free slots on the device, with the available and needed count
+	#[error("device has {0} free slots, {1} needed")]
+	InsufficientSlots(usize, usize),
 	/// Application specific error
 	#[error("App Error: | {0} {1}")]
 	AppSpecific(u16, String),
@@ -110,6 +128,8 @@ pub enum LedgerHIDError {
 pub enum APDUErrorCodes {
 	/// No error
 	NoError = 0x9000,
+	/// Still working, poll again for the answer
+	Heartbeat = 0x9001,
 	/// Execution error
 	ExecutionError = 0x6400,
 	/// Wrong length
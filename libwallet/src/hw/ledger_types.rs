@@ -1,9 +1,35 @@
 //!  Types associated with Ledger. Could be split in another way
 
+use ed25519_dalek::Signature as DalekSignature;
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+use crate::grin_util::secp::constants::{
+	COMPACT_SIGNATURE_SIZE, COMPRESSED_PUBLIC_KEY_SIZE, PEDERSEN_COMMITMENT_SIZE,
+};
+use crate::grin_util::secp::key::PublicKey;
+use crate::grin_util::secp::pedersen::Commitment;
+use crate::grin_util::secp::{Secp256k1, Signature};
+use crate::grin_util::static_secp_instance;
+use crate::hw::ledger_error::{LedgerAppError, TransportError};
 //use std::sync::{Arc, Mutex, Weak};
 //use futures::future;
 
+/// BIP-44 purpose
+pub const BIP44_PURPOSE: u32 = 44;
+/// Registered SLIP-44 coin type for Grin
+pub const GRIN_COIN_TYPE: u32 = 592;
+/// Flag marking a BIP-32 path segment as hardened
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// Maximum length of an account id sent to the device
+pub const MAX_ACCOUNT_ID_LEN: usize = 32;
+
+/// Capability bit: device can display Slatepack addresses
+pub const CAPABILITY_SLATEPACK_ADDRESS: u8 = 0x01;
+/// Capability bit: device can display Tor onion v3 addresses
+pub const CAPABILITY_ONION_ADDRESS: u8 = 0x02;
+
 /// Chunk payload type. An APDU message is broken up in packets.
 /// This enum is used to indicate whether a packet is the first one, an append packet,
 /// or the last one.
@@ -14,6 +40,17 @@ pub enum ChunkPayloadType {
 	Add = 0x01,
 	/// Last chunk
 	Last = 0x02,
+	/// Poll for the answer after a heartbeat
+	Poll = 0x03,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// Progress reported by the device during a long operation
+pub enum ProgressEvent {
+	/// The device is still working on the start command
+	StartHeartbeat,
+	/// The device is still working on the chunk with the given index
+	Heartbeat(usize),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -34,6 +71,21 @@ pub struct Version {
 	pub target_id: [u8; 4],
 }
 
+impl Version {
+	/// Name of the device model, if the target ID is a known one.
+	pub fn device_model(&self) -> Option<&'static str> {
+		match u32::from_be_bytes(self.target_id) {
+			0x3110_0002 | 0x3110_0003 | 0x3110_0004 => Some("Nano S"),
+			0x3300_0004 => Some("Nano X"),
+			0x3310_0004 => Some("Nano S Plus"),
+			0x3320_0004 => Some("Stax"),
+			0x3330_0004 => Some("Flex"),
+			0x3100_0002 | 0x3101_0004 => Some("Blue"),
+			_ => None,
+		}
+	}
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 /// App Information
 pub struct AppInfo {
@@ -62,3 +114,287 @@ pub struct AppInfo {
 	#[serde(rename(serialize = "flagsPINValidated"))]
 	pub flag_pin_validated: bool,
 }
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// Address format the device can display
+pub enum AddressFormat {
+	/// Bech32 Slatepack address
+	Slatepack,
+	/// Tor onion v3 address
+	Onion,
+}
+
+impl AddressFormat {
+	/// Map the capability flags reported by the device to address formats.
+	pub fn from_capabilities(flags: u8) -> Vec<AddressFormat> {
+		let mut formats = vec![];
+		if flags & CAPABILITY_SLATEPACK_ADDRESS != 0 {
+			formats.push(AddressFormat::Slatepack);
+		}
+		if flags & CAPABILITY_ONION_ADDRESS != 0 {
+			formats.push(AddressFormat::Onion);
+		}
+		formats
+	}
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// Name of a wallet account, as sent to the device
+pub struct AccountId(String);
+
+impl AccountId {
+	/// Check the account name is non-empty, at most MAX_ACCOUNT_ID_LEN long,
+	/// and only made of ASCII letters, digits, '-' and '_'.
+	pub fn new(account: &str) -> Result<AccountId, LedgerAppError> {
+		if account.is_empty() {
+			return Err(LedgerAppError::InvalidAccountId("empty".to_owned()));
+		}
+		if account.len() > MAX_ACCOUNT_ID_LEN {
+			return Err(LedgerAppError::InvalidAccountId(format!(
+				"longer than {} characters",
+				MAX_ACCOUNT_ID_LEN
+			)));
+		}
+		if let Some(c) = account
+			.chars()
+			.find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+		{
+			return Err(LedgerAppError::InvalidAccountId(format!(
+				"invalid character {:?}",
+				c
+			)));
+		}
+		Ok(AccountId(account.to_owned()))
+	}
+
+	/// Account name
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+
+	/// Account name bytes, as sent to the device
+	pub fn as_bytes(&self) -> &[u8] {
+		self.0.as_bytes()
+	}
+}
+
+impl TryFrom<&str> for AccountId {
+	type Error = LedgerAppError;
+
+	fn try_from(account: &str) -> Result<AccountId, LedgerAppError> {
+		AccountId::new(account)
+	}
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+/// BIP-44 derivation path of a wallet account, m/44'/coin_type'/account'
+pub struct DerivationPath {
+	/// Coin type
+	pub coin_type: u32,
+	/// Account index
+	pub account: u32,
+}
+
+impl DerivationPath {
+	/// Path of the given account under the Grin coin type.
+	pub fn new(account: u32) -> DerivationPath {
+		DerivationPath {
+			coin_type: GRIN_COIN_TYPE,
+			account,
+		}
+	}
+
+	/// Hardened path segments, in order.
+	pub fn segments(&self) -> [u32; 3] {
+		[
+			BIP44_PURPOSE | HARDENED,
+			self.coin_type | HARDENED,
+			self.account | HARDENED,
+		]
+	}
+
+	/// Serialize the segments big-endian, as expected by the device.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		self.segments()
+			.iter()
+			.flat_map(|s| s.to_be_bytes().to_vec())
+			.collect()
+	}
+}
+
+impl Default for DerivationPath {
+	fn default() -> DerivationPath {
+		DerivationPath::new(0)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Data returned by the device after signing as sender
+pub struct SignerResponse {
+	/// Public nonce, generated from the secret nonce on device
+	pub pub_nonce: PublicKey,
+	/// Commitment
+	pub commitment: Commitment,
+	/// Partial signature
+	pub partial_sig: Signature,
+	/// Payment proof signature, if a payment proof was requested
+	pub payment_proof_sig: Option<DalekSignature>,
+}
+
+impl SignerResponse {
+	/// Parse the response data: public nonce, commitment and partial
+	/// signature, optionally followed by the payment proof signature.
+	pub fn from_bytes(data: &[u8]) -> Result<SignerResponse, LedgerAppError> {
+		let secp = static_secp_instance();
+		let secp = secp.lock();
+
+		let (pub_nonce, rest) = split_response(data, COMPRESSED_PUBLIC_KEY_SIZE)?;
+		let (commitment, rest) = split_response(rest, PEDERSEN_COMMITMENT_SIZE)?;
+		let (partial_sig, rest) = split_response(rest, COMPACT_SIGNATURE_SIZE)?;
+
+		Ok(SignerResponse {
+			pub_nonce: parse_public_key(&secp, pub_nonce)?,
+			commitment: Commitment::from_vec(commitment.to_vec()),
+			partial_sig: parse_signature(&secp, partial_sig)?,
+			payment_proof_sig: parse_optional_dalek_signature(rest)?,
+		})
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Data returned by the device after signing as receiver
+pub struct ReceiverResponse {
+	/// Public nonce, generated from the secret nonce on device
+	pub pub_nonce: PublicKey,
+	/// Kernel commitment
+	pub kernel_commitment: Commitment,
+	/// Partial signature
+	pub partial_sig: Signature,
+	/// Payment proof signature, if the sender requested a payment proof
+	pub payment_proof_sig: Option<DalekSignature>,
+}
+
+impl ReceiverResponse {
+	/// Parse the response data: public nonce, kernel commitment and partial
+	/// signature, optionally followed by the payment proof signature.
+	pub fn from_bytes(data: &[u8]) -> Result<ReceiverResponse, LedgerAppError> {
+		let secp = static_secp_instance();
+		let secp = secp.lock();
+
+		let (pub_nonce, rest) = split_response(data, COMPRESSED_PUBLIC_KEY_SIZE)?;
+		let (kernel_commitment, rest) = split_response(rest, PEDERSEN_COMMITMENT_SIZE)?;
+		let (partial_sig, rest) = split_response(rest, COMPACT_SIGNATURE_SIZE)?;
+
+		Ok(ReceiverResponse {
+			pub_nonce: parse_public_key(&secp, pub_nonce)?,
+			kernel_commitment: Commitment::from_vec(kernel_commitment.to_vec()),
+			partial_sig: parse_signature(&secp, partial_sig)?,
+			payment_proof_sig: parse_optional_dalek_signature(rest)?,
+		})
+	}
+}
+
+/// Split the first `len` bytes off a device response.
+fn split_response(data: &[u8], len: usize) -> Result<(&[u8], &[u8]), LedgerAppError> {
+	if data.len() < len {
+		return Err(LedgerAppError::TransportError(
+			TransportError::ResponseTooShort,
+		));
+	}
+	Ok(data.split_at(len))
+}
+
+fn parse_public_key(secp: &Secp256k1, data: &[u8]) -> Result<PublicKey, LedgerAppError> {
+	PublicKey::from_slice(secp, data).map_err(|_| LedgerAppError::InvalidPK)
+}
+
+fn parse_signature(secp: &Secp256k1, data: &[u8]) -> Result<Signature, LedgerAppError> {
+	Signature::from_compact(secp, data).map_err(|_| LedgerAppError::InvalidSignature)
+}
+
+/// Parse the trailing payment proof signature, if any.
+fn parse_optional_dalek_signature(data: &[u8]) -> Result<Option<DalekSignature>, LedgerAppError> {
+	match data.len() {
+		0 => Ok(None),
+		ed25519_dalek::SIGNATURE_LENGTH => DalekSignature::try_from(data)
+			.map(Some)
+			.map_err(|_| LedgerAppError::InvalidSignature),
+		_ => Err(LedgerAppError::InvalidMessageSize),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn address_formats_from_capabilities() {
+		assert_eq!(AddressFormat::from_capabilities(0x00), vec![]);
+		assert_eq!(
+			AddressFormat::from_capabilities(CAPABILITY_SLATEPACK_ADDRESS),
+			vec![AddressFormat::Slatepack]
+		);
+		assert_eq!(
+			AddressFormat::from_capabilities(0x03),
+			vec![AddressFormat::Slatepack, AddressFormat::Onion]
+		);
+		// Unknown bits are ignored
+		assert_eq!(
+			AddressFormat::from_capabilities(0xF2),
+			vec![AddressFormat::Onion]
+		);
+	}
+
+	#[test]
+	fn default_derivation_path() {
+		let path = DerivationPath::default();
+		assert_eq!(path.coin_type, GRIN_COIN_TYPE);
+		assert_eq!(path.segments()[1], 592 | HARDENED);
+		assert_eq!(
+			path.to_bytes(),
+			vec![0x80, 0, 0, 44, 0x80, 0, 0x02, 0x50, 0x80, 0, 0, 0]
+		);
+		assert_eq!(DerivationPath::new(3).segments()[2], 3 | HARDENED);
+	}
+
+	#[test]
+	fn device_model_from_target_id() {
+		let version = |target_id| Version {
+			mode: 0,
+			major: 1,
+			minor: 0,
+			patch: 0,
+			locked: false,
+			target_id,
+		};
+		assert_eq!(
+			version([0x31, 0x10, 0x00, 0x04]).device_model(),
+			Some("Nano S")
+		);
+		assert_eq!(
+			version([0x33, 0x00, 0x00, 0x04]).device_model(),
+			Some("Nano X")
+		);
+		assert_eq!(
+			version([0x33, 0x10, 0x00, 0x04]).device_model(),
+			Some("Nano S Plus")
+		);
+		assert_eq!(version([0, 0, 0, 0]).device_model(), None);
+	}
+
+	#[test]
+	fn account_id_validation() {
+		assert_eq!(AccountId::new("default").unwrap().as_str(), "default");
+		assert!(AccountId::new("savings_2-old").is_ok());
+		assert!(AccountId::try_from("a").is_ok());
+		assert!(AccountId::new(&"a".repeat(MAX_ACCOUNT_ID_LEN)).is_ok());
+
+		assert_eq!(
+			AccountId::new(""),
+			Err(LedgerAppError::InvalidAccountId("empty".to_owned()))
+		);
+		assert!(AccountId::new(&"a".repeat(MAX_ACCOUNT_ID_LEN + 1)).is_err());
+		assert!(AccountId::new("my account").is_err());
+		assert!(AccountId::new("compte\u{e9}").is_err());
+	}
+}
@@ -15,6 +15,7 @@
 //! Wrapper for Ledger device.
 
 use bincode;
+use futures::executor::block_on;
 
 use std::str;
 use std::collections::BTreeMap;
@@ -28,12 +29,15 @@ use crate::grin_core::core::transaction::Transaction;
 use crate::grin_core::core::{Inputs, Output, TxKernel, FeeFields};
 
 use crate::hw::apdu_types::*;
-use crate::hw::ledger_error::{Error, LedgerAppError};
+use crate::hw::ledger_error::{
+	APDUErrorCodes, Error, LedgerAppError, LedgerHIDError, TransportError,
+};
 use crate::hw::ledger_types::*;
 use crate::hw::transportnativehid::*;
 
 use crate::grin_util::secp::key::{PublicKey, SecretKey};
-use crate::grin_util::secp::pedersen::Commitment;
+use crate::grin_util::secp::constants::MAX_PROOF_SIZE;
+use crate::grin_util::secp::pedersen::{Commitment, RangeProof};
 use crate::grin_util::secp::Signature;
 use crate::grin_util::{secp, static_secp_instance};
 use crate::grin_keychain::{BlindSum, BlindingFactor, Keychain, Identifier, SwitchCommitmentType};
@@ -52,6 +56,8 @@ const INS_DEVICE_RESET: u8 = 0x05;
 //const INS_PUT_KEY: u8 = 0x06;
 //const INS_APP_INFO: u8 = 0x07;
 const INS_GET_NUM_SLOTS: u8 = 0x08;
+const INS_GET_CAPABILITIES: u8 = 0x09;
+const INS_GET_ACCOUNT_PUBKEY: u8 = 0x0A;
 //const INS_GEN_KEY_DERIVATION: u8 = 0x00;
 //const INS_GENERATE_KEYPAIR: u8 = 0x00;
 //const INS_RESET: u8 = 0x00;
@@ -60,38 +66,87 @@ const INS_GET_NUM_SLOTS: u8 = 0x08;
 const INS_SEND: u8 = 0x0B;
 const INS_RECEIVE: u8 = 0x0C; // TODO
 const INS_GET_RANGEPROOF: u8 = 0x0D; // TODO
+const INS_SELECT_INPUT: u8 = 0x0E; // TODO
 
 // Constants
 const PROTOCOL_VERSION: u8 = 4;
+const GRIN_APP_NAME: &str = "Grin";
 
 const CLA_APP_INFO: u8 = 0xb0; //
+const INS_APP_INFO: u8 = 0x01; // Answered by the dashboard and every app.
 
 const CLA_DEVICE_INFO: u8 = 0xe0; //
 const INS_DEVICE_INFO: u8 = 0x01;
 
 const USER_MESSAGE_CHUNK_SIZE: usize = 250; //
+const RANGEPROOFS_PER_REQUEST: usize = 4; // Bounded by the device buffer.
+const MAX_HEARTBEATS: usize = 120; // Polls before giving up on a busy device.
+
+// Sign instruction parameters
+const P2_CONFIRM_ALL_OUTPUTS: u8 = 0x01; // Show every output on the device.
 
 /// Definition of a LedgerDevice.
 /// This will be used to access a Ledger hardware wallet.
 pub struct LedgerDevice {
-	/// The underlying HID device
-	_ledger: TransportNativeHID,
+	/// Transport to the underlying HID device
+	apdu_transport: APDUTransport,
+	/// Require on-device confirmation of every output when signing
+	confirm_all_outputs: bool,
+	/// Called with the heartbeats the device sends while signing
+	on_progress: Option<Box<dyn Fn(ProgressEvent) + Send>>,
+	/// Account public keys fetched during this session
+	account_pubkeys: BTreeMap<DerivationPath, PublicKey>,
 }
 
 impl LedgerDevice {
 	/// Get the underlying HID device.
-	pub fn new() -> LedgerDevice {
+	pub fn new() -> Result<LedgerDevice, LedgerHIDError> {
+		let ledger = TransportNativeHID::new()?;
+		Ok(LedgerDevice::with_transport(APDUTransport::new(ledger)))
+	}
+
+	/// Use the given transport to talk to the device.
+	pub fn with_transport(apdu_transport: APDUTransport) -> LedgerDevice {
 		LedgerDevice {
-			_ledger: TransportNativeHID::new().expect("Could not get a device"),
+			apdu_transport,
+			confirm_all_outputs: false,
+			on_progress: None,
+			account_pubkeys: BTreeMap::new(),
 		}
 	}
 
 	///
-	pub fn init(&mut self) -> Result<(), Error> {
-		self._ledger = TransportNativeHID::new().expect("Could not get a device");
+	pub fn init(&mut self) -> Result<(), LedgerHIDError> {
+		let ledger = TransportNativeHID::new()?;
+		self.apdu_transport = APDUTransport::new(ledger);
+		self.invalidate();
 		Ok(())
 	}
 
+	/// Forget the cached account public keys, e.g. after reconnecting, as a
+	/// different device or seed may now be attached.
+	pub fn invalidate(&mut self) {
+		self.account_pubkeys.clear();
+	}
+
+	/// Force per-output confirmation on the device when signing, rather than
+	/// letting it summarize the outputs.
+	pub fn set_confirm_all_outputs(&mut self, confirm_all_outputs: bool) {
+		self.confirm_all_outputs = confirm_all_outputs;
+	}
+
+	/// Report heartbeats while the device waits for the user, e.g. to tell
+	/// them to check the screen.
+	pub fn set_on_progress(&mut self, on_progress: impl Fn(ProgressEvent) + Send + 'static) {
+		self.on_progress = Some(Box::new(on_progress));
+	}
+
+	fn report_progress(&self, event: ProgressEvent) {
+		if let Some(on_progress) = &self.on_progress {
+			on_progress(event);
+		}
+	}
+
 	///
 	fn connect(&mut self) -> Result<(), Error> {
 		LedgerDevice::disconnect(self);
@@ -121,14 +176,29 @@ impl LedgerDevice {
 	///
 	pub fn reset(&mut self) -> Result<(), Error> {
 		//let cmd = LedgerDevice::set_command_header_noopt(self, INS_RESET, 0x00, 0x00);
-		//self._ledger.exchange(&cmd);
+		//block_on(self.apdu_transport.exchange(&cmd));
+		Ok(())
+	}
+
+	/// Return the app to idle, dropping any half-sent command. Call this when
+	/// a flow fails midway, so the next one doesn't hit a device still
+	/// waiting for chunks.
+	pub async fn abort_session(&mut self) -> Result<(), LedgerAppError> {
+		let cmd = APDUCommand {
+			cla: 0xE0,
+			ins: INS_DEVICE_RESET,
+			p1: 0x00,
+			p2: 0x00,
+			data: Vec::new(),
+		};
+		exchange_checked(&self.apdu_transport, &cmd).await?;
 		Ok(())
 	}
 
 	///
 	pub fn send_simple(&mut self, ins: u8, p1: u8) -> () {
 		let cmd = LedgerDevice::set_command_header_noopt(self, ins, p1, 0x00);
-		self._ledger.exchange(&cmd);
+		block_on(self.apdu_transport.exchange(&cmd));
 	}
 
 	///
@@ -185,7 +255,7 @@ impl LedgerDevice {
 
 		LedgerDevice::send_secret(self, "".to_string(), 0);
 
-		//self._ledger.exchange(&cmd);
+		//block_on(self.apdu_transport.exchange(&cmd));
 
 		LedgerDevice::receive_secret(self);
 	}
@@ -195,7 +265,7 @@ impl LedgerDevice {
 		println!("get_version");
 		let cmd = LedgerDevice::set_command_header_noopt(self, INS_GET_VERSION, 0x00, 0x00);
 		println!("cmd: {:?}", cmd);
-		let result = self._ledger.exchange(&cmd).expect("Error during exchange");
+		let result = block_on(self.apdu_transport.exchange(&cmd)).expect("Error during exchange");
 		println!("{:?}", result);
 	}
 
@@ -212,10 +282,8 @@ impl LedgerDevice {
 	///
 	pub fn get_public_key(&mut self) -> () {}
 
-	///
-	pub async fn get_app_name(&mut self) -> Result<(), LedgerAppError> {
-		let _ledger = TransportNativeHID::new().expect("Could not get a device");
-		let apdu_transport = APDUTransport::new(_ledger);
+	/// Get the name of the app open on the device.
+	pub async fn get_app_name(&self) -> Result<String, LedgerAppError> {
 		//let cmd = LedgerDevice::set_command_header_noopt(self, INS_GET_APP_NAME, 0x00, 0x00);
 		let cmd = APDUCommand {
 			cla: 0xE0,
@@ -224,24 +292,45 @@ impl LedgerDevice {
 			p2: 0x00,
 			data: Vec::new(),
 		};
-		println!("cmd: {:?}", cmd);
-		let response = apdu_transport.exchange(&cmd).await?;
-		let description = self.map_apdu_error_description(response.retcode);
-		println!("description: {:?}", description);
-		println!("response: {:?}", response);
+		let response = exchange_checked(&self.apdu_transport, &cmd).await?;
+		device_str("app name", &response.data)
+	}
 
-		let app_name_bytes = &response.data[0..4];
-		println!("app_name_bytes: {:?}", app_name_bytes);
+	/// Get the name of the running app from the OS rather than the app, so
+	/// it works on the dashboard too, where the name is "BOLOS".
+	pub async fn get_running_app_name(&self) -> Result<String, LedgerAppError> {
+		let cmd = APDUCommand {
+			cla: CLA_APP_INFO,
+			ins: INS_APP_INFO,
+			p1: 0x00,
+			p2: 0x00,
+			data: Vec::new(),
+		};
+		let response = exchange_checked(&self.apdu_transport, &cmd).await?;
+		// Format ID, then the length prefixed name, version and flags
+		let data = &response.data;
+		if data.len() < 2 {
+			return Err(TransportError::ResponseTooShort.into());
+		}
+		if data[0] != 0x01 {
+			return Err(LedgerAppError::InvalidFormatID);
+		}
+		let name_len = usize::from(data[1]);
+		if data.len() < 2 + name_len {
+			return Err(TransportError::ResponseTooShort.into());
+		}
+		Ok(device_str("app name", &data[2..2 + name_len])?.to_owned())
+	}
 
-		let app_name = str::from_utf8(app_name_bytes).map_err(|_e| LedgerAppError::Utf8)?;
-		println!("app_name: {:?}", app_name);
-		Ok(())
+	/// Check the Grin app is open, rather than the dashboard or another app,
+	/// so the user can be asked to open it before anything else fails.
+	pub async fn is_grin_app_open(&self) -> Result<bool, LedgerAppError> {
+		Ok(self.get_running_app_name().await? == GRIN_APP_NAME)
 	}
 
-	///
-	pub async fn get_num_slots(&mut self) -> Result<(), LedgerAppError> {
-		let _ledger = TransportNativeHID::new().expect("Could not get a device");
-		let apdu_transport = APDUTransport::new(_ledger);
+	/// Get the number of free transaction slots on the device. The count is
+	/// sent as 4 ASCII decimal digits, e.g. "0003"; NUL padding is ignored.
+	pub async fn get_num_slots(&self) -> Result<usize, LedgerAppError> {
 		//let cmd = LedgerDevice::set_command_header_noopt(self, INS_GET_NUM_SLOTS, 0x00, 0x00);
 		let cmd = APDUCommand {
 			cla: 0xE0,
@@ -250,17 +339,49 @@ impl LedgerDevice {
 			p2: 0x00,
 			data: Vec::new(),
 		};
-		let response = apdu_transport.exchange(&cmd).await?;
-		let description = self.map_apdu_error_description(response.retcode);
-		let num_slots_bytes = &response.data[0..4]; // TODO
-		let num_slots = str::from_utf8(num_slots_bytes).map_err(|_e| LedgerAppError::Utf8)?;
-		println!("num_slots_bytes: {:?}", num_slots_bytes);
-		println!("num_slots: {:?}", num_slots);
+		let response = exchange_checked(&self.apdu_transport, &cmd).await?;
+		if response.data.len() < 4 {
+			return Err(TransportError::ResponseTooShort.into());
+		}
+		let num_slots = device_str("num slots", &response.data[0..4])?;
+		let num_slots = num_slots.trim_end_matches('\0');
+		num_slots.parse::<usize>().map_err(|_| {
+			LedgerAppError::InvalidNumber("num slots".to_owned(), num_slots.to_owned())
+		})
+	}
+
+	/// Check the device has at least `needed` free slots before starting to
+	/// sign, so a full device doesn't fail halfway through the confirmations.
+	/// Every transaction being signed holds one slot until it is finalized.
+	pub async fn ensure_capacity(&self, needed: usize) -> Result<(), LedgerAppError> {
+		let available = self.get_num_slots().await?;
+		if available < needed {
+			return Err(LedgerAppError::InsufficientSlots(available, needed));
+		}
 		Ok(())
 	}
 
+	/// Get the address formats the device can display.
+	pub async fn supported_address_formats(&self) -> Result<Vec<AddressFormat>, LedgerAppError> {
+		let cmd = APDUCommand {
+			cla: 0xE0,
+			ins: INS_GET_CAPABILITIES,
+			p1: 0x00,
+			p2: 0x00,
+			data: Vec::new(),
+		};
+		let response = exchange_checked(&self.apdu_transport, &cmd).await?;
+		let flags = response
+			.data
+			.first()
+			.ok_or(LedgerAppError::TransportError(
+				TransportError::ResponseTooShort,
+			))?;
+		Ok(AddressFormat::from_capabilities(*flags))
+	}
+
 	pub fn start_send_tx(self, 
-			account: &str, 
+			account: &AccountId, 
 			const output: Output, 
 			const input: Input, 
 			const fee: FeeFields, 
@@ -283,30 +404,53 @@ impl LedgerDevice {
 		Ok()
 	}
 
-	pub fn get_account_pubkey(self, 
-		const account: &str) 
-		-> Result<(), Error>
-	{
-		let data = ;
-		let response = apdu_transport.exchange(&cmd).await?;
-		let description = self.map_apdu_error_description(response.retcode);
-		Ok()
-	}
+	/// Get the public key of the account at the given path. Keys are cached
+	/// for the session, so only the first lookup of a path talks to the device.
+	pub async fn get_account_pubkey(
+		&mut self,
+		path: &DerivationPath,
+	) -> Result<PublicKey, LedgerAppError> {
+		if let Some(pubkey) = self.account_pubkeys.get(path) {
+			return Ok(*pubkey);
+		}
 
-	pub fn select_input(self, 
-		const id: Identifier, 
-		const value: u64, 
-		const switch_commitment_type: SwitchCommitmentType) 
-		-> Result<(), Error>
-	{
+		let cmd = APDUCommand {
+			cla: 0xE0,
+			ins: INS_GET_ACCOUNT_PUBKEY,
+			p1: 0x00,
+			p2: 0x00,
+			data: path.to_bytes(),
+		};
+		let response = exchange_checked(&self.apdu_transport, &cmd).await?;
+		let pubkey = {
+			let secp = static_secp_instance();
+			let secp = secp.lock();
+			PublicKey::from_slice(&secp, &response.data).map_err(|_| LedgerAppError::InvalidPK)?
+		};
+		self.account_pubkeys.insert(*path, pubkey);
+		Ok(pubkey)
+	}
 
-		let data = ;
-		let response = apdu_transport.exchange(&cmd).await?;
-		let description = self.map_apdu_error_description(response.retcode);
+	/// Add an input to the transaction being built on the device.
+	pub async fn select_input(
+		&self,
+		id: &Identifier,
+		value: u64,
+		switch_commitment_type: SwitchCommitmentType,
+	) -> Result<(), LedgerAppError> {
+		let cmd = APDUCommand {
+			cla: 0xE0,
+			ins: INS_SELECT_INPUT,
+			p1: 0x00,
+			p2: 0x00,
+			// Same layout as a single rangeproof request
+			data: rangeproof_request(&[(id.clone(), value, switch_commitment_type)]),
+		};
+		exchange_checked(&self.apdu_transport, &cmd).await?;
 		Ok(())
 	}
 
-	pub fn select_output(self, const account: &str) -> Result<(), Error>
+	pub fn select_output(self, const account: &AccountId) -> Result<(), Error>
 	{
 		let data = ;
 		let response = apdu_transport.exchange(&cmd).await?;
@@ -315,7 +459,7 @@ impl LedgerDevice {
 	}
 
 	pub fn get_payment_proof(self, 
-		const account: &str, 
+		const account: &AccountId, 
 		const value: u64, 
 		const commitment: Commitment, 
 		const sender_address: DalekPublicKey) 
@@ -329,7 +473,7 @@ impl LedgerDevice {
 	}
 
 	pub fn get_commitment(self, 
-			const account: &str, 
+			const account: &AccountId, 
 			const id: Identifier, 
 			const value: u64, 
 			const switch_commitment_type: SwitchCommitmentType) 
@@ -344,7 +488,7 @@ impl LedgerDevice {
 	}
 
 	pub fn get_private_nonce(self, 
-			const account: &str, 
+			const account: &AccountId, 
 			const private_nonce: ) 
 			-> Result<(), Error>
 	{
@@ -403,16 +547,16 @@ impl LedgerDevice {
 		Ok()
 	}
 
-	pub fn get_tor_pub_key(self, const account: &str) -> Result<(), Error>
+	pub fn get_tor_pub_key(self, const account: &AccountId) -> Result<(), Error>
 	{
-		let data = account;
+		let data = account.as_bytes().to_vec();
 		let response = apdu_transport.exchange(&cmd).await?;
 		let description = self.map_apdu_error_description(response.retcode);
 		Ok()
 	}
 
 	pub fn get_tor_tx_sig(
-		const account: &str, 
+		const account: &AccountId, 
 		const value: u64,
 		const commitment: Commitment,
 		const sender_address: DalekPublicKey,
@@ -435,46 +579,32 @@ impl LedgerDevice {
 	}
 
 	/* Round 1*/
-	///
+	/// Returns the public nonce, commitment and partial signature produced
+	/// by the device.
 	pub async fn sign_sender<K: Keychain>(
 		&mut self,
 		keychain: &K,
 		context: &Context,
 		data: TransactionData,
 		sender_input_params: SenderInputParams,
-	) -> Result<(), LedgerAppError> {
+	) -> Result<SignerResponse, LedgerAppError> {
 		// Convert data to binary, before sending to Ledger device.
 
 		//let psgt = self.create_psgt(data);
 		// serialize PSGT
 		//serialize_hex(&psgt);
 
-		// Set slate as data.
-		//let xs: Vec<u8> = bincode::serialize(&slate).unwrap();
-		//let cmd = LedgerDevice::set_command_header(self, INS_SEND, 0x00, 0x00, xs);
-
-		// Return pub_nonce and commitment, generated from secret nonce on device.
-		//let pub_once =
-		//let commitment =
-
-		// TODO
-		/*
-				let response = apdu_transport.exchange(&cmd).await?;
-				if response.retcode != APDUErrorCodes::NoError as u16 {
-					return Err(LedgerAppError::TransportError(
-						TransportError::APDUExchangeError,
-					));
-				}
-		*/
-
-		// Send
-
-		// Get hex
-		//let hex = "70736274ff010"; // TODO
-						   // deserialize PSGT
-		//let deserialized = deserialize(hex);
-
-		Ok(())
+		let xs = device_tx_data(&data)?;
+		self.ensure_capacity(1).await?;
+		let cmd = sign_command(INS_SEND, self.confirm_all_outputs, Vec::new());
+
+		// pub_nonce and commitment are generated from the secret nonce on device.
+		let response = self
+			.send_chunks(&self.apdu_transport, &cmd, &xs, &mut |event| {
+				self.report_progress(event)
+			})
+			.await?;
+		SignerResponse::from_bytes(&response.data)
 	}
 
 	///
@@ -488,54 +618,26 @@ impl LedgerDevice {
 		Ok(())
 	}
 
-	/// Returns payment nonce, proof signature,
+	/// Returns public nonce, kernel commitment, partial signature and
+	/// payment proof signature.
 	pub async fn sign_receiver<K: Keychain>(
 		&mut self,
 		keychain: &K,
 		context: &Context,
 		data: TransactionData,
-	) -> Result<(), LedgerAppError> {
-		//let cmd = LedgerDevice::set_command_header_noopt(self, INS_RECEIVE, 0x00, 0x00);
-
+	) -> Result<ReceiverResponse, LedgerAppError> {
 		// Set data
-		let tx_info = Vec::new();
-		//let psgt = self.create_psgt(data);
-
-		// serialize PSGT
-		//serialize_hex(&psgt);
-
-		let cmd = APDUCommand {
-			cla: 0xE0,
-			ins: INS_RECEIVE,
-			p1: 0x00,
-			p2: 0x00,
-			data: tx_info,
-		};
-
-		/*
-				let response = apdu_transport.exchange(&cmd).await?;
-				if response.retcode != APDUErrorCodes::NoError as u16 {
-					return Err(LedgerAppError::TransportError(
-						TransportError::APDUExchangeError,
-					));
-				}
-		*/
+		let tx_info = device_tx_data(&data)?;
+		self.ensure_capacity(1).await?;
+		let cmd = sign_command(INS_RECEIVE, self.confirm_all_outputs, Vec::new());
 
 		// Convert response data to information we need
-
-		// Get hex
-		//let hex = "70736274ff010"; // TODO
-
-		// deserialize PSGT
-		//let deserialized = deserialize(hex);
-
-		// Get payment proof signature from response data.
-		//let paymentProofSignature : DalekSignature =
-
-		//kernel_commitment
-		//pubnonce
-
-		Ok(())
+		let response = self
+			.send_chunks(&self.apdu_transport, &cmd, &tx_info, &mut |event| {
+				self.report_progress(event)
+			})
+			.await?;
+		ReceiverResponse::from_bytes(&response.data)
 	}
 
 	pub async fn sign_finalize<K: Keychain>(
@@ -587,32 +689,39 @@ impl LedgerDevice {
 		Ok(())
 	}
 
-	/// Translate a retcode into an error message.
-	pub fn map_apdu_error_description(&mut self, retcode: u16) -> &'static str {
-		match retcode {
-			0x6400 => "APDU_CODE_EXECUTION_ERROR - No information given (NV-Ram not changed)",
-			0x6700 => "APDU_CODE_WRONG_LENGTH - Wrong length",
-			0x6982 => "APDU_CODE_EMPTY_BUFFER",
-			0x6983 => "APDU_CODE_OUTPUT_BUFFER_TOO_SMALL - ",
-			0x6984 => "APDU_CODE_DATA_INVALID - data reversibly blocked (invalidated)",
-			0x6985 => "APDU_CODE_CONDITIONS_NOT_SATISFIED - Conditions of use not satisfied",
-			0x6986 => "APDU_CODE_COMMAND_NOT_ALLOWED - Command not allowed (no current EF)",
-			0x6A80 => "APDU_CODE_BAD_KEY_HANDLE - The parameters in the data field are incorrect",
-			0x6B00 => "APDU_CODE_INVALIDP1P2 - Wrong parameter(s) P1-P2",
-			0x6D00 => "APDU_CODE_INS_NOT_SUPPORTED - Instruction code not supported or invalid",
-			0x6E00 => "APDU_CODE_CLA_NOT_SUPPORTED - Class not supported",
-			0x6F00 => "APDU_CODE_UNKNOWN - ",
-			0x6F01 => "APDU_CODE_SIGN_VERIFY_ERROR - ",
-			_ => "[APDU_ERROR] Unknown",
+	/// Get the rangeproofs for several outputs, batching up to
+	/// RANGEPROOFS_PER_REQUEST outputs per device request.
+	pub async fn get_rangeproofs(
+		&self,
+		outputs: &[(Identifier, u64, SwitchCommitmentType)],
+	) -> Result<Vec<RangeProof>, LedgerAppError> {
+		let mut proofs = Vec::with_capacity(outputs.len());
+		for batch in outputs.chunks(RANGEPROOFS_PER_REQUEST) {
+			let cmd = APDUCommand {
+				cla: 0xE0,
+				ins: INS_GET_RANGEPROOF,
+				p1: batch.len() as u8,
+				p2: 0x00,
+				data: rangeproof_request(batch),
+			};
+			let response = exchange_checked(&self.apdu_transport, &cmd).await?;
+			proofs.extend(parse_rangeproofs(&response.data, batch.len())?);
 		}
+		Ok(proofs)
+	}
+
+	/// Translate a retcode into an error message.
+	pub fn map_apdu_error_description(&self, retcode: u16) -> &'static str {
+		apdu_error_description(retcode)
 	}
 
 	/// Stream a long request in chunks
 	pub async fn send_chunks(
-		&mut self,
+		&self,
 		apdu_transport: &APDUTransport,
 		start_command: &APDUCommand,
 		message: &[u8],
+		on_progress: &mut dyn FnMut(ProgressEvent),
 	) -> Result<APDUAnswer, LedgerAppError> {
 		// Returns an iterator over a slice in chunks, with the given size.
 		let chunks = message.chunks(USER_MESSAGE_CHUNK_SIZE);
@@ -630,14 +739,15 @@ impl LedgerDevice {
 		}
 
 		// If retcode isn't OK, map to error description.
-		let mut response = apdu_transport.exchange(start_command).await?;
-		if response.retcode != 0x9000 {
-			return Err(LedgerAppError::AppSpecific(
-				response.retcode,
-				self.map_apdu_error_description(response.retcode)
-					.to_string(),
-			));
-		}
+		let response = apdu_transport.exchange(start_command).await?;
+		let mut response = wait_for_answer(
+			apdu_transport,
+			start_command,
+			response,
+			ProgressEvent::StartHeartbeat,
+			on_progress,
+		)
+		.await?;
 
 		// Send message chunks
 		let last_chunk_index = chunks.len() - 1;
@@ -660,13 +770,14 @@ impl LedgerDevice {
 
 			// response is of type APDUAnswer
 			response = apdu_transport.exchange(&command).await?;
-			if response.retcode != 0x9000 {
-				return Err(LedgerAppError::AppSpecific(
-					response.retcode,
-					self.map_apdu_error_description(response.retcode)
-						.to_string(),
-				));
-			}
+			response = wait_for_answer(
+				apdu_transport,
+				&command,
+				response,
+				ProgressEvent::Heartbeat(packet_idx),
+				on_progress,
+			)
+			.await?;
 		}
 
 		// If we get to here, return the response.
@@ -674,6 +785,150 @@ impl LedgerDevice {
 	}
 }
 
+/// Translate a retcode into an error message.
+fn apdu_error_description(retcode: u16) -> &'static str {
+	match retcode {
+		0x6400 => "APDU_CODE_EXECUTION_ERROR - No information given (NV-Ram not changed)",
+		0x6700 => "APDU_CODE_WRONG_LENGTH - Wrong length",
+		0x6982 => "APDU_CODE_EMPTY_BUFFER",
+		0x6983 => "APDU_CODE_OUTPUT_BUFFER_TOO_SMALL - ",
+		0x6984 => "APDU_CODE_DATA_INVALID - data reversibly blocked (invalidated)",
+		0x6985 => "APDU_CODE_CONDITIONS_NOT_SATISFIED - Conditions of use not satisfied",
+		0x6986 => "APDU_CODE_COMMAND_NOT_ALLOWED - Command not allowed (no current EF)",
+		0x6A80 => "APDU_CODE_BAD_KEY_HANDLE - The parameters in the data field are incorrect",
+		0x6B00 => "APDU_CODE_INVALIDP1P2 - Wrong parameter(s) P1-P2",
+		0x6D00 => "APDU_CODE_INS_NOT_SUPPORTED - Instruction code not supported or invalid",
+		0x6E00 => "APDU_CODE_CLA_NOT_SUPPORTED - Class not supported",
+		0x6F00 => "APDU_CODE_UNKNOWN - ",
+		0x6F01 => "APDU_CODE_SIGN_VERIFY_ERROR - ",
+		_ => "[APDU_ERROR] Unknown",
+	}
+}
+
+/// Exchange a command, mapping a retcode other than success to an error.
+async fn exchange_checked(
+	apdu_transport: &APDUTransport,
+	command: &APDUCommand,
+) -> Result<APDUAnswer, LedgerAppError> {
+	let response = apdu_transport.exchange(command).await?;
+	check_retcode(response.retcode)?;
+	Ok(response)
+}
+
+/// Serialize transaction data in the layout the device expects.
+fn device_tx_data(data: &TransactionData) -> Result<Vec<u8>, LedgerAppError> {
+	data.to_device_bytes().map_err(|e| LedgerAppError::Serialization(e.to_string()))
+}
+
+/// Keep polling while the device answers with heartbeats, as it's still
+/// working on the command, then check the final retcode. Gives up after
+/// MAX_HEARTBEATS so a stuck device can't hang the wallet.
+async fn wait_for_answer(
+	apdu_transport: &APDUTransport,
+	command: &APDUCommand,
+	mut response: APDUAnswer,
+	event: ProgressEvent,
+	on_progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<APDUAnswer, LedgerAppError> {
+	let mut heartbeats = 0;
+	while response.retcode == APDUErrorCodes::Heartbeat as u16 {
+		if heartbeats == MAX_HEARTBEATS {
+			return Err(LedgerAppError::TooManyHeartbeats(MAX_HEARTBEATS));
+		}
+		heartbeats += 1;
+		on_progress(event);
+		let poll = APDUCommand {
+			cla: command.cla,
+			ins: command.ins,
+			p1: ChunkPayloadType::Poll as u8,
+			p2: 0,
+			data: Vec::new(),
+		};
+		response = apdu_transport.exchange(&poll).await?;
+	}
+	check_retcode(response.retcode)?;
+	Ok(response)
+}
+
+/// Map a retcode other than success to an error. The device answers
+/// ConditionsNotSatisfied when the user declines on screen.
+fn check_retcode(retcode: u16) -> Result<(), LedgerAppError> {
+	if retcode == APDUErrorCodes::NoError as u16 {
+		Ok(())
+	} else if retcode == APDUErrorCodes::ConditionsNotSatisfied as u16 {
+		Err(LedgerAppError::UserRejected)
+	} else {
+		Err(LedgerAppError::AppSpecific(
+			retcode,
+			apdu_error_description(retcode).to_string(),
+		))
+	}
+}
+
+/// Serialize rangeproof requests: identifier, big-endian value and switch
+/// commitment type of each output.
+fn rangeproof_request(outputs: &[(Identifier, u64, SwitchCommitmentType)]) -> Vec<u8> {
+	let mut data = vec![];
+	for (id, value, switch_commitment_type) in outputs {
+		data.extend_from_slice(&id.to_bytes());
+		data.extend_from_slice(&value.to_be_bytes());
+		data.push(match switch_commitment_type {
+			SwitchCommitmentType::None => 0x00,
+			SwitchCommitmentType::Regular => 0x01,
+		});
+	}
+	data
+}
+
+/// Parse `count` rangeproofs, each prefixed by its big-endian u16 length.
+fn parse_rangeproofs(data: &[u8], count: usize) -> Result<Vec<RangeProof>, LedgerAppError> {
+	let too_short = || LedgerAppError::TransportError(TransportError::ResponseTooShort);
+	let mut proofs = Vec::with_capacity(count);
+	let mut rest = data;
+	for _ in 0..count {
+		if rest.len() < 2 {
+			return Err(too_short());
+		}
+		let plen = (usize::from(rest[0]) << 8) + usize::from(rest[1]);
+		if plen > MAX_PROOF_SIZE {
+			return Err(LedgerAppError::InvalidMessageSize);
+		}
+		if rest.len() < 2 + plen {
+			return Err(too_short());
+		}
+		let mut proof = [0u8; MAX_PROOF_SIZE];
+		proof[..plen].copy_from_slice(&rest[2..2 + plen]);
+		proofs.push(RangeProof { proof, plen });
+		rest = &rest[2 + plen..];
+	}
+	if !rest.is_empty() {
+		return Err(LedgerAppError::InvalidMessageSize);
+	}
+	Ok(proofs)
+}
+
+/// Build a sign command, setting the confirm-all-outputs flag in p2.
+fn sign_command(ins: u8, confirm_all_outputs: bool, data: Vec<u8>) -> APDUCommand {
+	let p2 = if confirm_all_outputs {
+		P2_CONFIRM_ALL_OUTPUTS
+	} else {
+		0x00
+	};
+	APDUCommand {
+		cla: 0xE0,
+		ins,
+		p1: 0x00,
+		p2,
+		data,
+	}
+}
+
+/// Decode a string returned by the device, keeping the field name and
+/// the offending bytes if it isn't valid UTF-8.
+fn device_str<'a>(field: &str, bytes: &'a [u8]) -> Result<&'a str, LedgerAppError> {
+	str::from_utf8(bytes).map_err(|_| LedgerAppError::Utf8(field.to_owned(), hex::encode(bytes)))
+}
+
 /// Only used for testing purposes. Set specific key on device.
 fn put_keys() -> () {
 	/*
@@ -705,3 +960,446 @@ fn reset() -> bool {
 	return true;
 }
 */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::grin_keychain::ExtKeychain;
+	use crate::grin_util::secp::key::SecretKey;
+	use crate::hw::mock::MockExchange;
+	use crate::ErrorKind;
+	use std::sync::{Arc, Mutex};
+
+	fn test_public_key(byte: u8) -> PublicKey {
+		let secp = static_secp_instance();
+		let secp = secp.lock();
+		let sec_key = SecretKey::from_slice(&secp, &[byte; 32]).unwrap();
+		PublicKey::from_secret_key(&secp, &sec_key).unwrap()
+	}
+
+	fn public_key_bytes(key: &PublicKey) -> Vec<u8> {
+		let secp = static_secp_instance();
+		let secp = secp.lock();
+		key.serialize_vec(&secp, true).to_vec()
+	}
+
+	fn test_sign_inputs() -> (ExtKeychain, Context, TransactionData) {
+		let keychain = ExtKeychain::from_random_seed(true).unwrap();
+		let context = {
+			let secp = static_secp_instance();
+			let secp = secp.lock();
+			Context::new(&secp, &ExtKeychain::derive_key_id(2, 0, 0, 0, 0), true, true)
+		};
+		let data = TransactionData {
+			inputs: Inputs::FeaturesAndCommit(vec![]),
+			outputs: vec![],
+			kernels: vec![],
+			tko: BlindingFactor::zero(),
+			proof_sig: None,
+		};
+		(keychain, context, data)
+	}
+
+	#[test]
+	fn sign_sender_response() {
+		let pub_nonce = test_public_key(1);
+		let commit_key = test_public_key(2);
+		let mut data = public_key_bytes(&pub_nonce);
+		// Commitments use the same 33 byte compressed layout
+		data.extend(public_key_bytes(&commit_key));
+		data.extend(vec![0x11; 64]);
+		data.extend(vec![0x00; 64]);
+
+		// Free slots, start command, then a single chunk of transaction data
+		let mock = MockExchange::new(vec![
+			(b"0001".to_vec(), 0x9000),
+			(vec![], 0x9000),
+			(data.clone(), 0x9000),
+		]);
+		let commands = mock.commands();
+		let mut device = LedgerDevice::with_transport(APDUTransport::new(mock));
+		let (keychain, context, tx_data) = test_sign_inputs();
+		let tx_bytes = tx_data.to_device_bytes().unwrap();
+		let response = block_on(device.sign_sender(
+			&keychain,
+			&context,
+			tx_data,
+			SenderInputParams {},
+		))
+		.unwrap();
+
+		assert_eq!(response.pub_nonce, pub_nonce);
+		assert_eq!(response.commitment.0.to_vec(), data[33..66].to_vec());
+		let expected_sig = {
+			let secp = static_secp_instance();
+			let secp = secp.lock();
+			Signature::from_compact(&secp, &[0x11; 64]).unwrap()
+		};
+		assert_eq!(response.partial_sig, expected_sig);
+		assert!(response.payment_proof_sig.is_some());
+
+		let commands = commands.lock().unwrap();
+		assert_eq!(commands[0][1], INS_GET_NUM_SLOTS);
+		assert_eq!(commands[1][1], INS_SEND);
+		assert_eq!(commands[2][5..], tx_bytes[..]);
+
+		// Payment proof signature is optional
+		let response = SignerResponse::from_bytes(&data[..130]).unwrap();
+		assert!(response.payment_proof_sig.is_none());
+
+		assert_eq!(
+			SignerResponse::from_bytes(&data[..100]),
+			Err(LedgerAppError::TransportError(
+				TransportError::ResponseTooShort
+			))
+		);
+	}
+
+	#[test]
+	fn sign_receiver_response() {
+		let pub_nonce = test_public_key(3);
+		let mut data = public_key_bytes(&pub_nonce);
+		data.extend(public_key_bytes(&test_public_key(4)));
+		data.extend(vec![0x22; 64]);
+		data.extend(vec![0x00; 64]);
+
+		let mock = MockExchange::new(vec![
+			(b"0001".to_vec(), 0x9000),
+			(vec![], 0x9000),
+			(data.clone(), 0x9000),
+		]);
+		let commands = mock.commands();
+		let mut device = LedgerDevice::with_transport(APDUTransport::new(mock));
+		let (keychain, context, tx_data) = test_sign_inputs();
+		let response = block_on(device.sign_receiver(&keychain, &context, tx_data)).unwrap();
+
+		assert_eq!(response.pub_nonce, pub_nonce);
+		assert_eq!(response.kernel_commitment.0.to_vec(), data[33..66].to_vec());
+		let expected_sig = {
+			let secp = static_secp_instance();
+			let secp = secp.lock();
+			Signature::from_compact(&secp, &[0x22; 64]).unwrap()
+		};
+		assert_eq!(response.partial_sig, expected_sig);
+		assert!(response.payment_proof_sig.is_some());
+		assert_eq!(commands.lock().unwrap()[1][1], INS_RECEIVE);
+
+		// Trailing bytes that aren't a signature
+		assert_eq!(
+			ReceiverResponse::from_bytes(&data[..140]),
+			Err(LedgerAppError::InvalidMessageSize)
+		);
+	}
+
+	#[test]
+	fn user_rejected_on_device() {
+		let mock = MockExchange::new(vec![(vec![], 0x6985), (vec![], 0x6A80)]);
+		let apdu_transport = APDUTransport::new(mock);
+		let cmd = sign_command(INS_SEND, false, vec![]);
+
+		let res = block_on(exchange_checked(&apdu_transport, &cmd));
+		assert_eq!(res.unwrap_err(), LedgerAppError::UserRejected);
+
+		// Other failures keep their description
+		match block_on(exchange_checked(&apdu_transport, &cmd)) {
+			Err(LedgerAppError::AppSpecific(0x6A80, _)) => (),
+			res => panic!("unexpected result: {:?}", res),
+		}
+	}
+
+	#[test]
+	fn sign_sender_user_rejected() {
+		let (keychain, context, data) = test_sign_inputs();
+		let mock = MockExchange::new(vec![(b"0001".to_vec(), 0x9000), (vec![], 0x6985)]);
+		let mut device = LedgerDevice::with_transport(APDUTransport::new(mock));
+		let res = block_on(device.sign_sender(&keychain, &context, data, SenderInputParams {}));
+		let err = res.unwrap_err();
+		assert_eq!(err, LedgerAppError::UserRejected);
+		assert_eq!(
+			crate::Error::from(err).kind(),
+			ErrorKind::HardwareUserRejected
+		);
+	}
+
+	#[test]
+	fn sign_sender_reports_progress() {
+		let (keychain, context, data) = test_sign_inputs();
+		let mock = MockExchange::new(vec![
+			(b"0001".to_vec(), 0x9000),
+			(vec![], 0x9001),
+			(vec![], 0x9000),
+			(vec![], 0x6985),
+		]);
+		let mut device = LedgerDevice::with_transport(APDUTransport::new(mock));
+		let events = Arc::new(Mutex::new(vec![]));
+		let recorded = events.clone();
+		device.set_on_progress(move |event| recorded.lock().unwrap().push(event));
+
+		let res = block_on(device.sign_sender(&keychain, &context, data, SenderInputParams {}));
+		assert_eq!(res.unwrap_err(), LedgerAppError::UserRejected);
+		assert_eq!(*events.lock().unwrap(), vec![ProgressEvent::StartHeartbeat]);
+	}
+
+	#[test]
+	fn sign_sender_no_free_slots() {
+		let (keychain, context, data) = test_sign_inputs();
+		let mock = MockExchange::new(vec![(b"0000".to_vec(), 0x9000)]);
+		let commands = mock.commands();
+		let mut device = LedgerDevice::with_transport(APDUTransport::new(mock));
+		let res = block_on(device.sign_sender(&keychain, &context, data, SenderInputParams {}));
+		assert_eq!(res.unwrap_err(), LedgerAppError::InsufficientSlots(0, 1));
+		// Nothing is sent for signing
+		assert_eq!(commands.lock().unwrap().len(), 1);
+	}
+
+	#[test]
+	fn get_rangeproofs_batched() {
+		let mut answer = vec![];
+		for byte in &[0x01u8, 0x02] {
+			answer.extend_from_slice(&675u16.to_be_bytes());
+			answer.extend(vec![*byte; 675]);
+		}
+		let mock = MockExchange::new(vec![(answer, 0x9000)]);
+		let commands = mock.commands();
+		let device = LedgerDevice::with_transport(APDUTransport::new(mock));
+
+		let outputs = vec![
+			(
+				ExtKeychain::derive_key_id(3, 1, 0, 0, 0),
+				1_000,
+				SwitchCommitmentType::Regular,
+			),
+			(
+				ExtKeychain::derive_key_id(3, 1, 0, 1, 0),
+				2_000,
+				SwitchCommitmentType::Regular,
+			),
+		];
+		let proofs = block_on(device.get_rangeproofs(&outputs)).unwrap();
+
+		assert_eq!(proofs.len(), 2);
+		assert_eq!(proofs[0].plen, 675);
+		assert_eq!(proofs[0].proof[674], 0x01);
+		assert_eq!(proofs[1].proof[0], 0x02);
+		assert_eq!(proofs[1].proof[675], 0x00);
+
+		// A single request carrying both outputs
+		let commands = commands.lock().unwrap();
+		assert_eq!(commands.len(), 1);
+		assert_eq!(commands[0][1], INS_GET_RANGEPROOF);
+		assert_eq!(commands[0][2], 2);
+		assert_eq!(commands[0][4] as usize, 2 * (17 + 8 + 1));
+	}
+
+	#[test]
+	fn ensure_capacity_checks_free_slots() {
+		let mock = MockExchange::new(vec![
+			(b"0003".to_vec(), 0x9000),
+			(b"3\0\0\0".to_vec(), 0x9000),
+			(b"3 \0\0".to_vec(), 0x9000),
+		]);
+		let device = LedgerDevice::with_transport(APDUTransport::new(mock));
+
+		assert!(block_on(device.ensure_capacity(3)).is_ok());
+		assert_eq!(
+			block_on(device.ensure_capacity(5)),
+			Err(LedgerAppError::InsufficientSlots(3, 5))
+		);
+		assert_eq!(
+			block_on(device.ensure_capacity(1)),
+			Err(LedgerAppError::InvalidNumber(
+				"num slots".to_owned(),
+				"3 ".to_owned()
+			))
+		);
+	}
+
+	#[test]
+	fn send_chunks_heartbeat() {
+		let mock = MockExchange::new(vec![
+			(vec![], 0x9000),
+			(vec![], 0x9001),
+			(vec![], 0x9001),
+			(vec![0x42], 0x9000),
+		]);
+		let commands = mock.commands();
+		let apdu_transport = APDUTransport::new(mock);
+		let device =
+			LedgerDevice::with_transport(APDUTransport::new(MockExchange::new(vec![])));
+
+		let start_command = sign_command(INS_SEND, false, vec![]);
+		let mut events = vec![];
+		let response = block_on(device.send_chunks(
+			&apdu_transport,
+			&start_command,
+			&[0xAB; 10],
+			&mut |event| events.push(event),
+		))
+		.unwrap();
+
+		assert_eq!(response.data, vec![0x42]);
+		assert_eq!(
+			events,
+			vec![ProgressEvent::Heartbeat(0), ProgressEvent::Heartbeat(0)]
+		);
+		let commands = commands.lock().unwrap();
+		assert_eq!(commands.len(), 4);
+		assert_eq!(commands[1][2], ChunkPayloadType::Last as u8);
+		assert_eq!(commands[3][2], ChunkPayloadType::Poll as u8);
+	}
+
+	#[test]
+	fn send_chunks_start_heartbeat() {
+		let mock = MockExchange::new(vec![
+			(vec![], 0x9001),
+			(vec![], 0x9000),
+			(vec![0x42], 0x9000),
+		]);
+		let commands = mock.commands();
+		let apdu_transport = APDUTransport::new(mock);
+		let device =
+			LedgerDevice::with_transport(APDUTransport::new(MockExchange::new(vec![])));
+
+		let start_command = sign_command(INS_SEND, false, vec![]);
+		let mut events = vec![];
+		let response = block_on(device.send_chunks(
+			&apdu_transport,
+			&start_command,
+			&[0xAB; 10],
+			&mut |event| events.push(event),
+		))
+		.unwrap();
+
+		assert_eq!(response.data, vec![0x42]);
+		assert_eq!(events, vec![ProgressEvent::StartHeartbeat]);
+		let commands = commands.lock().unwrap();
+		assert_eq!(commands.len(), 3);
+		assert_eq!(commands[1][2], ChunkPayloadType::Poll as u8);
+		assert_eq!(commands[2][2], ChunkPayloadType::Last as u8);
+	}
+
+	#[test]
+	fn send_chunks_heartbeat_limit() {
+		// A device answering nothing but heartbeats
+		let mut answers = vec![(vec![], 0x9000)];
+		answers.extend(vec![(vec![], 0x9001); MAX_HEARTBEATS + 1]);
+		let mock = MockExchange::new(answers);
+		let commands = mock.commands();
+		let apdu_transport = APDUTransport::new(mock);
+		let device =
+			LedgerDevice::with_transport(APDUTransport::new(MockExchange::new(vec![])));
+
+		let start_command = sign_command(INS_SEND, false, vec![]);
+		let mut heartbeats = 0;
+		let result = block_on(device.send_chunks(
+			&apdu_transport,
+			&start_command,
+			&[0xAB; 10],
+			&mut |_| heartbeats += 1,
+		));
+
+		assert_eq!(
+			result.err(),
+			Some(LedgerAppError::TooManyHeartbeats(MAX_HEARTBEATS))
+		);
+		assert_eq!(heartbeats, MAX_HEARTBEATS);
+		// Start, chunk, then one poll per heartbeat
+		assert_eq!(commands.lock().unwrap().len(), 2 + MAX_HEARTBEATS);
+	}
+
+	#[test]
+	fn account_pubkey_cached() {
+		let pubkey = test_public_key(1);
+		let mock = MockExchange::new(vec![
+			(public_key_bytes(&pubkey), 0x9000),
+			(public_key_bytes(&pubkey), 0x9000),
+		]);
+		let commands = mock.commands();
+		let mut device = LedgerDevice::with_transport(APDUTransport::new(mock));
+
+		let path = DerivationPath::new(1);
+		assert_eq!(block_on(device.get_account_pubkey(&path)).unwrap(), pubkey);
+		assert_eq!(block_on(device.get_account_pubkey(&path)).unwrap(), pubkey);
+		assert_eq!(commands.lock().unwrap().len(), 1);
+		assert_eq!(commands.lock().unwrap()[0][5..], path.to_bytes()[..]);
+
+		// Fetched again once the cache is invalidated
+		device.invalidate();
+		assert_eq!(block_on(device.get_account_pubkey(&path)).unwrap(), pubkey);
+		assert_eq!(commands.lock().unwrap().len(), 2);
+	}
+
+	#[test]
+	fn grin_app_open() {
+		let app_info = |name: &str, version: &str| {
+			let mut data = vec![0x01, name.len() as u8];
+			data.extend_from_slice(name.as_bytes());
+			data.push(version.len() as u8);
+			data.extend_from_slice(version.as_bytes());
+			// Flags
+			data.extend_from_slice(&[0x01, 0x00]);
+			data
+		};
+		let mock = MockExchange::new(vec![
+			(app_info("Grin", "0.1.0"), 0x9000),
+			(app_info("BOLOS", "2.1.0"), 0x9000),
+			(app_info("Bitcoin", "2.0.6"), 0x9000),
+			(vec![0x02, 0x04], 0x9000),
+			(vec![], 0x6F00),
+		]);
+		let commands = mock.commands();
+		let device = LedgerDevice::with_transport(APDUTransport::new(mock));
+
+		assert_eq!(block_on(device.is_grin_app_open()), Ok(true));
+		assert_eq!(commands.lock().unwrap()[0], vec![CLA_APP_INFO, INS_APP_INFO, 0, 0, 0]);
+		// Dashboard
+		assert_eq!(block_on(device.is_grin_app_open()), Ok(false));
+		// Another app
+		assert_eq!(block_on(device.is_grin_app_open()), Ok(false));
+		// Unknown format and other failures are still reported
+		assert_eq!(
+			block_on(device.is_grin_app_open()),
+			Err(LedgerAppError::InvalidFormatID)
+		);
+		assert!(block_on(device.is_grin_app_open()).is_err());
+	}
+
+	#[test]
+	fn abort_session_sends_reset() {
+		let mock = MockExchange::new(vec![(vec![], 0x9000), (vec![], 0x6400)]);
+		let commands = mock.commands();
+		let mut device = LedgerDevice::with_transport(APDUTransport::new(mock));
+
+		assert!(block_on(device.abort_session()).is_ok());
+		assert_eq!(commands.lock().unwrap()[0], vec![0xE0, INS_DEVICE_RESET, 0, 0, 0]);
+
+		assert!(block_on(device.abort_session()).is_err());
+		assert_eq!(commands.lock().unwrap().len(), 2);
+	}
+
+	#[test]
+	fn sign_command_confirm_all_outputs() {
+		let summarized = sign_command(INS_SEND, false, vec![0xAB]);
+		let confirm_all = sign_command(INS_SEND, true, vec![0xAB]);
+		assert_eq!(summarized.serialize(), vec![0xE0, INS_SEND, 0x00, 0x00, 0x01, 0xAB]);
+		assert_eq!(
+			confirm_all.serialize(),
+			vec![0xE0, INS_SEND, 0x00, P2_CONFIRM_ALL_OUTPUTS, 0x01, 0xAB]
+		);
+	}
+
+	#[test]
+	fn device_str_utf8_error() {
+		assert_eq!(device_str("app name", b"Grin").unwrap(), "Grin");
+
+		let err = device_str("app name", &[0x47, 0xff, 0xfe]).unwrap_err();
+		assert_eq!(
+			err,
+			LedgerAppError::Utf8("app name".to_owned(), "47fffe".to_owned())
+		);
+		assert_eq!(
+			err.to_string(),
+			"Utf8 conversion error in app name: 0x47fffe"
+		);
+	}
+}
@@ -0,0 +1,184 @@
+// Copyright 2021 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mock transports, answering APDU commands without a Ledger device.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use trait_async::trait_async;
+
+use crate::hw::apdu_types::*;
+use crate::hw::ledger_error::*;
+
+/// Exchange returning canned answers in order, and recording the
+/// serialized commands it receives.
+pub struct MockExchange {
+	answers: Mutex<VecDeque<APDUAnswer>>,
+	commands: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MockExchange {
+	/// Create a mock answering with the given (data, retcode) pairs.
+	pub fn new(answers: Vec<(Vec<u8>, u16)>) -> Self {
+		MockExchange {
+			answers: Mutex::new(
+				answers
+					.into_iter()
+					.map(|(data, retcode)| APDUAnswer { data, retcode })
+					.collect(),
+			),
+			commands: Arc::new(Mutex::new(vec![])),
+		}
+	}
+
+	/// Handle on the commands received so far, usable after the mock has
+	/// been moved into an APDUTransport.
+	pub fn commands(&self) -> Arc<Mutex<Vec<Vec<u8>>>> {
+		self.commands.clone()
+	}
+}
+
+#[trait_async]
+impl Exchange for MockExchange {
+	async fn exchange(&self, command: &APDUCommand) -> Result<APDUAnswer, TransportError> {
+		self.commands.lock().unwrap().push(command.serialize());
+		self.answers
+			.lock()
+			.unwrap()
+			.pop_front()
+			.ok_or(TransportError::APDUExchangeError)
+	}
+}
+
+/// Recorded session: serialized commands, each with the raw answer (data
+/// followed by the big-endian retcode).
+pub type ApduLog = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Exchange forwarding to another one, e.g. a real device, and recording
+/// the session so it can be replayed with a ReplayExchange.
+pub struct ApduRecorder {
+	inner: Box<dyn Exchange>,
+	log: Arc<Mutex<ApduLog>>,
+}
+
+impl ApduRecorder {
+	/// Record the session with the given exchange.
+	pub fn new(inner: impl Exchange + 'static) -> Self {
+		ApduRecorder {
+			inner: Box::new(inner),
+			log: Arc::new(Mutex::new(vec![])),
+		}
+	}
+
+	/// Handle on the session recorded so far.
+	pub fn log(&self) -> Arc<Mutex<ApduLog>> {
+		self.log.clone()
+	}
+}
+
+#[trait_async]
+impl Exchange for ApduRecorder {
+	async fn exchange(&self, command: &APDUCommand) -> Result<APDUAnswer, TransportError> {
+		let answer = self.inner.exchange(command).await?;
+		let mut raw_answer = answer.data.clone();
+		raw_answer.extend_from_slice(&answer.retcode.to_be_bytes());
+		self.log
+			.lock()
+			.unwrap()
+			.push((command.serialize(), raw_answer));
+		Ok(answer)
+	}
+}
+
+/// Exchange replaying a recorded session. Fails if a command differs from
+/// the recorded one, or once the recording is exhausted.
+pub struct ReplayExchange {
+	log: Mutex<VecDeque<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl ReplayExchange {
+	/// Replay the given session, in order.
+	pub fn new(log: ApduLog) -> Self {
+		ReplayExchange {
+			log: Mutex::new(log.into_iter().collect()),
+		}
+	}
+}
+
+#[trait_async]
+impl Exchange for ReplayExchange {
+	async fn exchange(&self, command: &APDUCommand) -> Result<APDUAnswer, TransportError> {
+		let (recorded_command, raw_answer) = self
+			.log
+			.lock()
+			.unwrap()
+			.pop_front()
+			.ok_or(TransportError::APDUExchangeError)?;
+		if recorded_command != command.serialize() {
+			return Err(TransportError::APDUExchangeError);
+		}
+		if raw_answer.len() < 2 {
+			return Err(TransportError::ResponseTooShort);
+		}
+		Ok(APDUAnswer::from_answer(raw_answer))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures::executor::block_on;
+
+	fn command(ins: u8, data: Vec<u8>) -> APDUCommand {
+		APDUCommand {
+			cla: 0xE0,
+			ins,
+			p1: 0x00,
+			p2: 0x00,
+			data,
+		}
+	}
+
+	#[test]
+	fn record_and_replay() {
+		let recorder = ApduRecorder::new(MockExchange::new(vec![
+			(b"Grin".to_vec(), 0x9000),
+			(vec![], 0x6985),
+		]));
+		let log = recorder.log();
+		let transport = APDUTransport::new(recorder);
+		block_on(transport.exchange(&command(0x04, vec![]))).unwrap();
+		block_on(transport.exchange(&command(0x0B, vec![1, 2, 3]))).unwrap();
+
+		let log = log.lock().unwrap().clone();
+		assert_eq!(log.len(), 2);
+		assert_eq!(log[1].1, vec![0x69, 0x85]);
+
+		let replay = APDUTransport::new(ReplayExchange::new(log.clone()));
+		let answer = block_on(replay.exchange(&command(0x04, vec![]))).unwrap();
+		assert_eq!(answer.data, b"Grin".to_vec());
+		assert_eq!(answer.retcode, 0x9000);
+		let answer = block_on(replay.exchange(&command(0x0B, vec![1, 2, 3]))).unwrap();
+		assert_eq!(answer.retcode, 0x6985);
+		// Recording exhausted
+		assert!(block_on(replay.exchange(&command(0x04, vec![]))).is_err());
+
+		// A command differing from the recording fails
+		let replay = APDUTransport::new(ReplayExchange::new(log));
+		assert_eq!(
+			block_on(replay.exchange(&command(0x05, vec![]))).err(),
+			Some(TransportError::APDUExchangeError)
+		);
+	}
+}
@@ -18,6 +18,8 @@ pub mod apdu_types;
 pub mod ledger_error;
 pub mod ledger_types;
 pub mod ledgerdevice;
+#[cfg(test)]
+pub mod mock;
 pub mod transportnativehid;
 
 pub use self::apdu_types::*;
@@ -16,7 +16,7 @@
 
 use log::info;
 use std::ffi::CStr;
-use std::sync::{Arc, Mutex, Weak};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
 // Contains convenience methods for encoding and decoding numbers in Big-endian
 use byteorder::{BigEndian, ReadBytesExt};
@@ -37,8 +37,9 @@ use crate::hw::ledger_error::*;
 
 const LEDGER_VID: u16 = 0x2c97; // Vendor ID
 const LEDGER_USAGE_PAGE: u16 = 0xFFA0; //
+const LEDGER_INTERFACE: i32 = 0; // Generic HID interface, used for APDU exchange.
 const LEDGER_CHANNEL: u16 = 0x0101; //
-const LEDGER_PACKET_SIZE: u8 = 64; // Size of the packet that is used to communicate with APDU packets.
+const LEDGER_PACKET_SIZE: usize = 64; // Default size of the packets used to communicate APDUs.
 const LEDGER_TIMEOUT: i32 = 10_000_000; //
 
 ///
@@ -48,16 +49,29 @@ unsafe impl Sync for TransportNativeHID {}
 #[allow(dead_code)]
 /// Wrapper for HID device
 pub struct TransportNativeHID {
-	api_mutex: Arc<Mutex<hidapi::HidApi>>,
+	// Fields drop in declaration order, so the device is closed before the
+	// api handle is released.
 	device: HidDevice,
 	device_mutex: Mutex<i32>,
+	packet_size: usize,
+	api_mutex: Arc<Mutex<hidapi::HidApi>>,
 }
 
 impl TransportNativeHID {
+	/// Find the Ledger device path.
 	#[cfg(not(target_os = "linux"))]
-	fn find_ledger_device_path(api: &hidapi::HidApi) -> Result<&CStr, LedgerHIDError> {
+	fn find_ledger_device_path(
+		api: &hidapi::HidApi,
+		product_id: Option<u16>,
+	) -> Result<&CStr, LedgerHIDError> {
 		for device in api.device_list() {
-			if device.vendor_id() == LEDGER_VID && device.usage_page() == LEDGER_USAGE_PAGE {
+			if is_ledger_interface(
+				device.vendor_id(),
+				device.product_id(),
+				device.usage_page(),
+				device.interface_number(),
+				product_id,
+			) {
 				return Ok(device.path());
 			}
 		}
@@ -66,12 +80,21 @@ impl TransportNativeHID {
 
 	/// Find the Ledger device path.
 	#[cfg(target_os = "linux")]
-	fn find_ledger_device_path(api: &hidapi::HidApi) -> Result<&CStr, LedgerHIDError> {
+	fn find_ledger_device_path(
+		api: &hidapi::HidApi,
+		product_id: Option<u16>,
+	) -> Result<&CStr, LedgerHIDError> {
 		// look at all devices, find the one that matched the LEDGER_VID.
 		for device in api.device_list() {
 			if device.vendor_id() == LEDGER_VID {
 				let usage_page = get_usage_page(&device.path())?;
-				if usage_page == LEDGER_USAGE_PAGE {
+				if is_ledger_interface(
+					device.vendor_id(),
+					device.product_id(),
+					usage_page,
+					device.interface_number(),
+					product_id,
+				) {
 					// If this all worked, return here.
 					return Ok(device.path());
 				}
@@ -83,17 +106,29 @@ impl TransportNativeHID {
 
 	/// Create a new TransportNativeHID.
 	pub fn new() -> Result<Self, LedgerHIDError> {
-		let apiwrapper = HIDAPIWRAPPER.lock().expect("Could not lock api wrapper");
-		let api_mutex = apiwrapper.get().expect("Error getting api_mutex");
-		let api = api_mutex.lock().expect("Could not lock");
+		TransportNativeHID::open(None)
+	}
+
+	/// Create a new TransportNativeHID for the Ledger with the given product id,
+	/// bypassing usage-page detection.
+	pub fn with_product_id(product_id: u16) -> Result<Self, LedgerHIDError> {
+		TransportNativeHID::open(Some(product_id))
+	}
+
+	/// Open the Ledger device, optionally restricted to a product id.
+	fn open(product_id: Option<u16>) -> Result<Self, LedgerHIDError> {
+		let api_mutex = hidapi_handle()?;
+		let api = lock(&api_mutex, "Could not lock hidapi")?;
 
 		// Find underlying device.
-		let device_path = TransportNativeHID::find_ledger_device_path(&api)?;
+		let device_path = TransportNativeHID::find_ledger_device_path(&api, product_id)?;
 		let device = api.open_path(&device_path)?;
+		let packet_size = get_packet_size(&device_path);
 
 		let ledger = TransportNativeHID {
 			device,
 			device_mutex: Mutex::new(0),
+			packet_size,
 			api_mutex: api_mutex.clone(),
 		};
 
@@ -102,27 +137,7 @@ impl TransportNativeHID {
 
 	/// Write a APDU command to the HID device
 	fn write_apdu(&self, channel: u16, apdu_command: &[u8]) -> Result<i32, LedgerHIDError> {
-		let command_length = apdu_command.len() as usize;
-		let mut in_data = Vec::with_capacity(command_length + 2);
-		in_data.push(((command_length >> 8) & 0xFF) as u8);
-		in_data.push((command_length & 0xFF) as u8);
-		// Appends all elements from apdu_command to in_data.
-		in_data.extend_from_slice(&apdu_command);
-
-		// Initialize buffer
-		let mut buffer = vec![0u8; LEDGER_PACKET_SIZE as usize];
-		buffer[0] = ((channel >> 8) & 0xFF) as u8; // channel big endian
-		buffer[1] = (channel & 0xFF) as u8; // channel big endian
-		buffer[2] = 0x05u8;
-
-		for (sequence_idx, chunk) in in_data
-			.chunks((LEDGER_PACKET_SIZE - 5) as usize)
-			.enumerate()
-		{
-			buffer[3] = ((sequence_idx >> 8) & 0xFF) as u8; // sequence_idx big endian
-			buffer[4] = (sequence_idx & 0xFF) as u8; // sequence_idx big endian
-			buffer[5..5 + chunk.len()].copy_from_slice(chunk);
-
+		for buffer in frame_apdu(channel, apdu_command, self.packet_size) {
 			info!("[{:3}] << {:}", buffer.len(), hex::encode(&buffer));
 
 			let result = self.device.write(&buffer);
@@ -143,83 +158,59 @@ impl TransportNativeHID {
 	}
 
 	///
-	fn read_apdu(&self, _channel: u16, apdu_answer: &mut Vec<u8>) -> Result<usize, LedgerHIDError> {
-		let mut buffer = vec![0u8; LEDGER_PACKET_SIZE as usize];
-		let mut sequence_idx = 0u16;
-		let mut expected_apdu_len = 0usize;
-
-		// Infinite loop.
-		loop {
-			//
-			let res = self.device.read_timeout(&mut buffer, LEDGER_TIMEOUT)?;
-
-			if (sequence_idx == 0 && res < 7) || res < 5 {
-				return Err(LedgerHIDError::Comm("Read error. Incomplete header"));
-			}
-
-			// Create a new cursor, wrapping an in-memory buffer.
-			// Allows to use Read and/or Write on them.
-			let mut rdr = Cursor::new(&buffer);
-
-			let _rcv_channel = rdr.read_u16::<BigEndian>()?;
-			let _rcv_tag = rdr.read_u8()?;
-			let rcv_seq_idx = rdr.read_u16::<BigEndian>()?;
-
-			// TODO: Check why windows returns a different channel/tag
-			//        if rcv_channel != channel {
-			//            return Err(Box::from(format!("Invalid channel: {}!={}", rcv_channel, channel )));
-			//        }
-			//        if rcv_tag != 0x05u8 {
-			//            return Err(Box::from("Invalid tag"));
-			//        }
-
-			if rcv_seq_idx != sequence_idx {
-				return Err(LedgerHIDError::Comm("Invalid sequence idx"));
-			}
-
-			if rcv_seq_idx == 0 {
-				expected_apdu_len = rdr.read_u16::<BigEndian>()? as usize;
-			}
-
-			let available: usize = buffer.len() - rdr.position() as usize;
-			let missing: usize = expected_apdu_len - apdu_answer.len();
-			let end_p = rdr.position() as usize + std::cmp::min(available, missing);
-
-			let new_chunk = &buffer[rdr.position() as usize..end_p];
-
-			info!("[{:3}] << {:}", new_chunk.len(), hex::encode(&new_chunk));
-
-			apdu_answer.extend_from_slice(new_chunk);
-
-			if apdu_answer.len() >= expected_apdu_len {
-				return Ok(apdu_answer.len());
-			}
-
-			sequence_idx += 1;
-		}
+	fn read_apdu(&self, _channel: u16) -> Result<Vec<u8>, LedgerHIDError> {
+		let packets = read_packets(self.packet_size, |buffer| {
+			Ok(self.device.read_timeout(buffer, LEDGER_TIMEOUT)?)
+		})?;
+
+		let answer = reassemble_apdu(&packets)?;
+		info!("[{:3}] << {:}", answer.len(), hex::encode(&answer));
+		Ok(answer)
 	}
 
 	///
 	pub fn exchange(&self, command: &APDUCommand) -> Result<APDUAnswer, LedgerHIDError> {
 		println!("TransportNativeHID exchange");
-		let _guard = self.device_mutex.lock().unwrap();
+		let _guard = lock(&self.device_mutex, "Could not lock device")?;
 
 		self.write_apdu(LEDGER_CHANNEL, &command.serialize())?;
 
-		let mut answer: Vec<u8> = Vec::with_capacity(256);
-		let res = self.read_apdu(LEDGER_CHANNEL, &mut answer)?;
+		// Answers can span many packets (rangeproofs are ~700 bytes), read_apdu
+		// keeps reading until the length announced in the first one is reached.
+		let answer = self.read_apdu(LEDGER_CHANNEL)?;
 
-		if res < 2 {
+		if answer.len() < 2 {
 			return Err(LedgerHIDError::Comm("response was too short"));
 		}
 
 		Ok(APDUAnswer::from_answer(answer))
 	}
+}
 
-	///
-	pub fn close() {
-		extern crate hidapi;
+/// Decide whether a HID interface is the Ledger APDU interface.
+/// If `product_id` is given, usage-page detection is skipped and the
+/// generic HID interface of that product is selected instead.
+fn is_ledger_interface(
+	vendor_id: u16,
+	product_id: u16,
+	usage_page: u16,
+	interface_number: i32,
+	product_override: Option<u16>,
+) -> bool {
+	if vendor_id != LEDGER_VID {
+		return false;
+	}
+	if let Some(pid) = product_override {
+		return product_id == pid && interface_number == LEDGER_INTERFACE;
 	}
+	if usage_page == LEDGER_USAGE_PAGE {
+		return true;
+	}
+	// On macOS, older hidapi builds (and some Apple Silicon hosts) report a
+	// usage page of 0 for every interface, since the IOKit usage page is only
+	// read for the top-level collection. Fall back to the interface number,
+	// which is stable across Ledger models.
+	cfg!(target_os = "macos") && usage_page == 0 && interface_number == LEDGER_INTERFACE
 }
 
 cfg_if! {
@@ -233,8 +224,8 @@ if #[cfg(target_os = "linux")] {
 		value: [u8; HID_MAX_DESCRIPTOR_SIZE],
 	}
 
-	///
-	fn get_usage_page(device_path: &CStr) -> Result<u16, LedgerHIDError>
+	/// Read the raw HID report descriptor of a device.
+	fn get_report_descriptor(device_path: &CStr) -> Result<Vec<u8>, LedgerHIDError>
 	{
 		// #define HIDIOCGRDESCSIZE	_IOR('H', 0x01, int)
 		// #define HIDIOCGRDESC		_IOR('H', 0x02, struct HidrawReportDescriptor)
@@ -266,46 +257,199 @@ if #[cfg(target_os = "linux")] {
 			hid_read_descr(fd, desc_raw_uninit.as_mut_ptr())?;
 			let desc_raw = desc_raw_uninit.assume_init();
 
-			let data = &desc_raw.value[..desc_raw.size as usize];
+			Ok(desc_raw.value[..desc_raw.size as usize].to_vec())
+		}
+	}
+
+	///
+	fn get_usage_page(device_path: &CStr) -> Result<u16, LedgerHIDError>
+	{
+		let descriptor = get_report_descriptor(device_path)?;
+		Ok(parse_usage_page(&descriptor))
+	}
 
-			let mut data_len;
-			let mut key_size;
-			let mut i = 0 as usize;
+	/// Get the HID packet size from the device descriptor, falling back to
+	/// the Ledger default.
+	fn get_packet_size(device_path: &CStr) -> usize
+	{
+		get_report_descriptor(device_path)
+			.ok()
+			.and_then(|descriptor| parse_packet_size(&descriptor))
+			// Must at least fit the 7 byte header of the first packet
+			.filter(|size| *size > 7)
+			.unwrap_or(LEDGER_PACKET_SIZE)
+	}
+} else {
+	/// hidapi doesn't expose the report descriptor on this platform.
+	fn get_packet_size(_device_path: &CStr) -> usize
+	{
+		LEDGER_PACKET_SIZE
+	}
+}}
 
-			while i < desc_size as usize {
-				let key = data[i];
-				let key_cmd = key & 0xFC;
+/// Size of the data in a HID report descriptor item.
+fn report_item_data_len(data: &[u8], i: usize) -> (usize, usize) {
+	let key = data[i];
+	if key & 0xF0 == 0xF0 {
+		// Long item: 3 byte prefix, data size in the second byte.
+		let data_len = data.get(i + 1).map(|l| *l as usize).unwrap_or(0);
+		(3, data_len)
+	} else {
+		let data_len = match key & 0x03 {
+			3 => 4,
+			n => n as usize,
+		};
+		(1, data_len)
+	}
+}
 
-				if key & 0xF0 == 0xF0 {
-					data_len = 0;
-					key_size = 3;
-					if i + 1 < desc_size as usize {
-						data_len = data[i + 1];
-					}
-				} else {
-					key_size = 1;
-					data_len = key & 0x03;
-					if data_len == 3 {
-						data_len = 4;
+/// Little-endian value of a short HID report descriptor item.
+fn report_item_value(data: &[u8], i: usize, data_len: usize) -> u32 {
+	data.iter()
+		.skip(i + 1)
+		.take(data_len)
+		.enumerate()
+		.fold(0u32, |acc, (j, b)| acc | (u32::from(*b) << (8 * j)))
+}
+
+/// Find the usage page in a HID report descriptor, or 0 if there is none.
+fn parse_usage_page(data: &[u8]) -> u16 {
+	let mut i = 0;
+	while i < data.len() {
+		let (key_size, data_len) = report_item_data_len(data, i);
+		if data[i] & 0xFC == 0x04 && key_size == 1 {
+			return report_item_value(data, i, data_len) as u16;
+		}
+		i += key_size + data_len;
+	}
+	0
+}
+
+/// Find the packet size in a HID report descriptor: report size (in bits)
+/// times report count, as declared for the first input or output report.
+fn parse_packet_size(data: &[u8]) -> Option<usize> {
+	let mut report_size = None;
+	let mut report_count = None;
+	let mut i = 0;
+	while i < data.len() {
+		let (key_size, data_len) = report_item_data_len(data, i);
+		if key_size == 1 {
+			let value = report_item_value(data, i, data_len) as usize;
+			match data[i] & 0xFC {
+				0x74 => report_size = Some(value),
+				0x94 => report_count = Some(value),
+				0x80 | 0x90 => {
+					if let (Some(size), Some(count)) = (report_size, report_count) {
+						let packet_size = size * count / 8;
+						if packet_size > 0 {
+							return Some(packet_size);
+						}
 					}
 				}
+				_ => (),
+			}
+		}
+		i += key_size + data_len;
+	}
+	None
+}
 
-				if key_cmd == 0x04 {
-					let usage_page = match data_len {
-						1 => u16::from(data[i + 1]),
-						2 => (u16::from(data[i + 2] )* 256 + u16::from(data[i + 1])),
-						_ => 0 as u16
-					};
+/// Split an APDU into HID packets of `packet_size` bytes. The APDU is prefixed
+/// with its length, and each packet starts with the channel, the 0x05 tag and
+/// its sequence index, all big endian.
+fn frame_apdu(channel: u16, apdu: &[u8], packet_size: usize) -> Vec<Vec<u8>> {
+	let apdu_length = apdu.len();
+	let mut in_data = Vec::with_capacity(apdu_length + 2);
+	in_data.push(((apdu_length >> 8) & 0xFF) as u8);
+	in_data.push((apdu_length & 0xFF) as u8);
+	in_data.extend_from_slice(apdu);
+
+	in_data
+		.chunks(packet_size - 5)
+		.enumerate()
+		.map(|(sequence_idx, chunk)| {
+			let mut buffer = vec![0u8; packet_size];
+			buffer[0] = ((channel >> 8) & 0xFF) as u8;
+			buffer[1] = (channel & 0xFF) as u8;
+			buffer[2] = 0x05u8;
+			buffer[3] = ((sequence_idx >> 8) & 0xFF) as u8;
+			buffer[4] = (sequence_idx & 0xFF) as u8;
+			buffer[5..5 + chunk.len()].copy_from_slice(chunk);
+			buffer
+		})
+		.collect()
+}
 
-					return Ok(usage_page);
-				}
+/// Number of HID packets needed for the answer whose first packet is given.
+/// The first packet carries the answer length after the 5 byte header.
+fn answer_packet_count(first_packet: &[u8], packet_size: usize) -> usize {
+	let expected_apdu_len = ((first_packet[5] as usize) << 8) | first_packet[6] as usize;
+	// Payload of the packets: the 2 length bytes, then the answer
+	let payload_size = packet_size - 5;
+	(expected_apdu_len + 2 + payload_size - 1) / payload_size
+}
 
-				i += (data_len + key_size) as usize;
-			}
+/// Reassemble an APDU answer from HID packets, checking their sequence
+/// indices. The first packet carries the answer length.
+/// Read the packets of an answer, keeping only the bytes each read returned.
+fn read_packets<F>(packet_size: usize, mut read: F) -> Result<Vec<Vec<u8>>, LedgerHIDError>
+where
+	F: FnMut(&mut [u8]) -> Result<usize, LedgerHIDError>,
+{
+	let mut buffer = vec![0u8; packet_size];
+	let mut packets = vec![];
+	let mut packet_count = 1;
+
+	while packets.len() < packet_count {
+		let res = read(&mut buffer)?;
+
+		if (packets.is_empty() && res < 7) || res < 5 {
+			return Err(LedgerHIDError::Comm("Read error. Incomplete header"));
+		}
+
+		if packets.is_empty() {
+			packet_count = answer_packet_count(&buffer, packet_size);
 		}
-		Ok(0)
+		// A short read leaves bytes of the previous packet in the buffer
+		packets.push(buffer[..res].to_vec());
 	}
-}}
+	Ok(packets)
+}
+
+fn reassemble_apdu(packets: &[Vec<u8>]) -> Result<Vec<u8>, LedgerHIDError> {
+	let mut answer = vec![];
+	let mut expected_apdu_len = 0usize;
+
+	for (sequence_idx, packet) in packets.iter().enumerate() {
+		if (sequence_idx == 0 && packet.len() < 7) || packet.len() < 5 {
+			return Err(LedgerHIDError::Comm("Read error. Incomplete header"));
+		}
+
+		let mut rdr = Cursor::new(packet);
+		let _rcv_channel = rdr.read_u16::<BigEndian>()?;
+		let _rcv_tag = rdr.read_u8()?;
+		let rcv_seq_idx = rdr.read_u16::<BigEndian>()?;
+
+		// TODO: Check why windows returns a different channel/tag
+		if rcv_seq_idx as usize != sequence_idx {
+			return Err(LedgerHIDError::Comm("Invalid sequence idx"));
+		}
+
+		if sequence_idx == 0 {
+			expected_apdu_len = rdr.read_u16::<BigEndian>()? as usize;
+		}
+
+		let start = rdr.position() as usize;
+		let missing = expected_apdu_len - answer.len();
+		let end = start + std::cmp::min(packet.len() - start, missing);
+		answer.extend_from_slice(&packet[start..end]);
+	}
+
+	if packets.is_empty() || answer.len() < expected_apdu_len {
+		return Err(LedgerHIDError::Comm("Read error. Incomplete answer"));
+	}
+	Ok(answer)
+}
 
 #[trait_async]
 impl Exchange for TransportNativeHID {
@@ -325,6 +469,20 @@ lazy_static! {
 		Arc::new(Mutex::new(HidApiWrapper::new()));
 }
 
+/// Get the shared hidapi handle, initializing hidapi if needed.
+fn hidapi_handle() -> Result<Arc<Mutex<hidapi::HidApi>>, LedgerHIDError> {
+	let apiwrapper = lock(&HIDAPIWRAPPER, "Could not lock hidapi wrapper")?;
+	apiwrapper.get()
+}
+
+/// Lock a mutex, returning an error rather than panicking if it's poisoned.
+fn lock<'a, T>(
+	mutex: &'a Mutex<T>,
+	msg: &'static str,
+) -> Result<MutexGuard<'a, T>, LedgerHIDError> {
+	mutex.lock().map_err(|_| LedgerHIDError::Comm(msg))
+}
+
 struct HidApiWrapper {
 	_api: RefCell<Weak<Mutex<hidapi::HidApi>>>,
 }
@@ -355,11 +513,10 @@ impl HidApiWrapper {
 }
 
 ///
-pub fn list_all_devices() -> () {
+pub fn list_all_devices() -> Result<(), LedgerHIDError> {
 	println!("list_all_devices");
-	let apiwrapper = HIDAPIWRAPPER.lock().expect("Could not lock api wrapper");
-	let api_mutex = apiwrapper.get().expect("Error getting api_mutex");
-	let api = api_mutex.lock().expect("Could not lock");
+	let api_mutex = hidapi_handle()?;
+	let api = lock(&api_mutex, "Could not lock hidapi")?;
 	println!("Listing all devices: ");
 	for device_info in api.device_list() {
 		println!(
@@ -373,15 +530,247 @@ pub fn list_all_devices() -> () {
 			device_info.product_string().unwrap_or_default()
 		);
 	}
+	Ok(())
 }
 
 ///
-pub fn ledger_device_path() -> () {
-	let apiwrapper = HIDAPIWRAPPER.lock().expect("Could not lock api wrapper");
-	let api_mutex = apiwrapper.get().expect("Error getting api_mutex");
-	let api = api_mutex.lock().expect("Could not lock");
+pub fn ledger_device_path() -> Result<(), LedgerHIDError> {
+	let api_mutex = hidapi_handle()?;
+	let api = lock(&api_mutex, "Could not lock hidapi")?;
 	// TODO: Extend to discover two devices
-	let ledger_path =
-		TransportNativeHID::find_ledger_device_path(&api).expect("Could not find a device");
+	let ledger_path = TransportNativeHID::find_ledger_device_path(&api, None)?;
 	println!("{:?}", ledger_path);
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const NANO_S_PID: u16 = 0x1011;
+
+	#[test]
+	fn ledger_interface_selection() {
+		assert!(is_ledger_interface(
+			LEDGER_VID,
+			NANO_S_PID,
+			LEDGER_USAGE_PAGE,
+			0,
+			None
+		));
+		// Not a Ledger
+		assert!(!is_ledger_interface(
+			0x1234,
+			NANO_S_PID,
+			LEDGER_USAGE_PAGE,
+			0,
+			None
+		));
+		// U2F interface of a Ledger
+		assert!(!is_ledger_interface(
+			LEDGER_VID, NANO_S_PID, 0xF1D0, 1, None
+		));
+	}
+
+	#[test]
+	fn ledger_interface_product_override() {
+		assert!(is_ledger_interface(
+			LEDGER_VID,
+			NANO_S_PID,
+			0,
+			0,
+			Some(NANO_S_PID)
+		));
+		assert!(!is_ledger_interface(
+			LEDGER_VID,
+			NANO_S_PID,
+			LEDGER_USAGE_PAGE,
+			0,
+			Some(0x4011)
+		));
+		assert!(!is_ledger_interface(
+			LEDGER_VID,
+			NANO_S_PID,
+			0,
+			1,
+			Some(NANO_S_PID)
+		));
+	}
+
+	#[test]
+	fn poisoned_lock_returns_error() {
+		let mutex = Arc::new(Mutex::new(0));
+		let poisoner = mutex.clone();
+		let _ = std::thread::spawn(move || {
+			let _guard = poisoner.lock().unwrap();
+			panic!("poison the lock");
+		})
+		.join();
+
+		match lock(&mutex, "Could not lock") {
+			Err(LedgerHIDError::Comm(msg)) => assert_eq!(msg, "Could not lock"),
+			_ => panic!("expected a communication error"),
+		}
+	}
+
+	#[test]
+	fn frame_single_packet() {
+		let packets = frame_apdu(LEDGER_CHANNEL, &[0xE0, 0x01, 0x00, 0x00, 0x00], 64);
+		assert_eq!(packets.len(), 1);
+		assert_eq!(packets[0].len(), 64);
+		assert_eq!(
+			&packets[0][..12],
+			&[0x01, 0x01, 0x05, 0x00, 0x00, 0x00, 0x05, 0xE0, 0x01, 0x00, 0x00, 0x00]
+		);
+		assert!(packets[0][12..].iter().all(|b| *b == 0));
+	}
+
+	#[test]
+	fn frame_multiple_packets() {
+		let apdu: Vec<u8> = (0..100u8).collect();
+		let packets = frame_apdu(LEDGER_CHANNEL, &apdu, 64);
+
+		// 2 length bytes + 100 bytes of data, 59 bytes per packet
+		assert_eq!(packets.len(), 2);
+		assert_eq!(&packets[0][..7], &[0x01, 0x01, 0x05, 0x00, 0x00, 0x00, 100]);
+		assert_eq!(&packets[0][7..], &apdu[..57]);
+		assert_eq!(&packets[1][..5], &[0x01, 0x01, 0x05, 0x00, 0x01]);
+		assert_eq!(&packets[1][5..48], &apdu[57..]);
+		assert!(packets[1][48..].iter().all(|b| *b == 0));
+	}
+
+	#[test]
+	fn reassemble_packets() {
+		let answer: Vec<u8> = (0..100u8).collect();
+		let packets = frame_apdu(LEDGER_CHANNEL, &answer, 64);
+		assert_eq!(reassemble_apdu(&packets).unwrap(), answer);
+
+		let packets = frame_apdu(LEDGER_CHANNEL, &[0x90, 0x00], 64);
+		assert_eq!(reassemble_apdu(&packets).unwrap(), vec![0x90, 0x00]);
+	}
+
+	#[test]
+	fn reassemble_large_answer() {
+		let mut raw_answer: Vec<u8> = (0..698).map(|i| (i % 251) as u8).collect();
+		raw_answer.extend_from_slice(&[0x90, 0x00]);
+		let packets = frame_apdu(LEDGER_CHANNEL, &raw_answer, 64);
+
+		// 702 bytes of payload, 59 per packet
+		assert_eq!(packets.len(), 12);
+		assert_eq!(answer_packet_count(&packets[0], 64), 12);
+
+		let answer = APDUAnswer::from_answer(reassemble_apdu(&packets).unwrap());
+		assert_eq!(answer.retcode, 0x9000);
+		assert_eq!(answer.data.len(), 698);
+		assert_eq!(answer.data[..], raw_answer[..698]);
+	}
+
+	#[test]
+	fn reassemble_bad_packets() {
+		let answer: Vec<u8> = (0..200u8).collect();
+		let mut packets = frame_apdu(LEDGER_CHANNEL, &answer, 64);
+		assert_eq!(packets.len(), 4);
+
+		// Out of order
+		packets.swap(1, 2);
+		match reassemble_apdu(&packets) {
+			Err(LedgerHIDError::Comm(msg)) => assert_eq!(msg, "Invalid sequence idx"),
+			_ => panic!("out of order packets accepted"),
+		}
+
+		// Missing the last packet
+		packets.swap(1, 2);
+		packets.pop();
+		match reassemble_apdu(&packets) {
+			Err(LedgerHIDError::Comm(msg)) => assert_eq!(msg, "Read error. Incomplete answer"),
+			_ => panic!("truncated answer accepted"),
+		}
+
+		// Header too short
+		match reassemble_apdu(&[vec![0x01, 0x01, 0x05, 0x00, 0x00, 0x00]]) {
+			Err(LedgerHIDError::Comm(msg)) => assert_eq!(msg, "Read error. Incomplete header"),
+			_ => panic!("short header accepted"),
+		}
+	}
+
+	#[test]
+	fn read_short_packet() {
+		let answer: Vec<u8> = (0..150u8).collect();
+		let packets = frame_apdu(LEDGER_CHANNEL, &answer, 64);
+		assert_eq!(packets.len(), 3);
+
+		// Each read copies the next packet, the middle one cut short
+		let mut reads = packets.clone();
+		reads[1].truncate(30);
+		let mut reads = reads.into_iter();
+		let read = |buffer: &mut [u8]| {
+			let packet = reads.next().unwrap();
+			buffer[..packet.len()].copy_from_slice(&packet);
+			Ok(packet.len())
+		};
+
+		let read_packets = read_packets(64, read).unwrap();
+		assert_eq!(read_packets[1].len(), 30);
+		match reassemble_apdu(&read_packets) {
+			Err(LedgerHIDError::Comm(msg)) => assert_eq!(msg, "Read error. Incomplete answer"),
+			_ => panic!("short packet accepted"),
+		}
+
+		// Full reads still reassemble
+		let mut reads = packets.clone().into_iter();
+		let read = |buffer: &mut [u8]| {
+			let packet = reads.next().unwrap();
+			buffer.copy_from_slice(&packet);
+			Ok(packet.len())
+		};
+		assert_eq!(
+			reassemble_apdu(&read_packets(64, read).unwrap()).unwrap(),
+			answer
+		);
+	}
+
+	// Report descriptor of the generic HID interface of a Ledger Nano S
+	const NANO_S_DESCRIPTOR: [u8; 34] = [
+		0x06, 0xA0, 0xFF, 0x09, 0x01, 0xA1, 0x01, 0x09, 0x03, 0x15, 0x00, 0x26, 0xFF, 0x00, 0x75,
+		0x08, 0x95, 0x40, 0x81, 0x08, 0x09, 0x04, 0x15, 0x00, 0x26, 0xFF, 0x00, 0x75, 0x08, 0x95,
+		0x40, 0x91, 0x08, 0xC0,
+	];
+
+	#[test]
+	fn report_descriptor_parsing() {
+		assert_eq!(parse_usage_page(&NANO_S_DESCRIPTOR), LEDGER_USAGE_PAGE);
+		assert_eq!(parse_packet_size(&NANO_S_DESCRIPTOR), Some(64));
+
+		// Same descriptor, declaring 128 byte reports
+		let mut descriptor = NANO_S_DESCRIPTOR;
+		descriptor[17] = 0x80;
+		descriptor[30] = 0x80;
+		assert_eq!(parse_packet_size(&descriptor), Some(128));
+
+		assert_eq!(parse_usage_page(&[]), 0);
+		assert_eq!(parse_packet_size(&[]), None);
+		// Truncated item
+		assert_eq!(parse_packet_size(&[0x75]), None);
+	}
+
+	#[test]
+	#[ignore] // Requires a connected Ledger device
+	fn reopen_after_drop() {
+		let transport = TransportNativeHID::new().unwrap();
+		drop(transport);
+		assert!(TransportNativeHID::new().is_ok());
+	}
+
+	#[cfg(target_os = "macos")]
+	#[test]
+	fn ledger_interface_macos_usage_page_quirk() {
+		assert!(is_ledger_interface(LEDGER_VID, NANO_S_PID, 0, 0, None));
+		assert!(!is_ledger_interface(LEDGER_VID, NANO_S_PID, 0, 1, None));
+	}
+
+	#[cfg(not(target_os = "macos"))]
+	#[test]
+	fn ledger_interface_zero_usage_page() {
+		assert!(!is_ledger_interface(LEDGER_VID, NANO_S_PID, 0, 0, None));
+	}
 }
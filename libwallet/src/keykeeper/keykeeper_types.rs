@@ -14,15 +14,25 @@
 
 //! Types associated with keykeeper
 
-use crate::grin_core::core::{Input, Inputs, Output, TxKernel};
+use crate::grin_core::core::{CommitWrapper, Input, Inputs, Output, TxKernel};
+use crate::grin_core::ser as grin_ser;
+use crate::grin_core::ser::{Readable, Reader, Writeable, Writer};
 use crate::grin_keychain::BlindingFactor;
 use crate::slate::PaymentInfo;
 //use crate::hw::ledger_error::{Error};
 use crate::{Error, ErrorKind};
+use ed25519_dalek::PublicKey as DalekPublicKey;
+use ed25519_dalek::Signature as DalekSignature;
+use std::convert::TryFrom;
+
+/// Version of the device serialization of TransactionData
+const DEVICE_TX_DATA_VERSION: u8 = 1;
+/// Protocol version of the grin types embedded in the device serialization
+const DEVICE_PROTOCOL_VERSION: grin_ser::ProtocolVersion = grin_ser::ProtocolVersion(4);
 
 pub trait KeyKeeper {
-	// Send instruction for getting the number of slots
-	fn get_num_slots(&mut self) -> Result<(), Error>;
+	// Send instruction for getting the number of free slots
+	fn get_num_slots(&mut self) -> Result<usize, Error>;
 
 	//
 	fn get_rangeproof(&mut self) -> Result<(), Error>;
@@ -49,6 +59,150 @@ pub struct TransactionData {
 	pub proof_sig: Option<PaymentInfo>,
 }
 
+impl TransactionData {
+	/// Serialize for the device. The encoding is versioned and does not depend
+	/// on serde or bincode defaults, so client and firmware stay in step.
+	pub fn to_device_bytes(&self) -> Result<Vec<u8>, Error> {
+		let mut vec = vec![];
+		grin_ser::serialize(&mut vec, DEVICE_PROTOCOL_VERSION, self)?;
+		Ok(vec)
+	}
+
+	/// Deserialize transaction data sent back by the device.
+	pub fn from_device_bytes(data: &[u8]) -> Result<TransactionData, Error> {
+		let mut reader = std::io::Cursor::new(data);
+		Ok(grin_ser::deserialize(&mut reader, DEVICE_PROTOCOL_VERSION)?)
+	}
+}
+
+impl Writeable for TransactionData {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), grin_ser::Error> {
+		writer.write_u8(DEVICE_TX_DATA_VERSION)?;
+		//0 means commit only inputs
+		//1 means inputs with features
+		match &self.inputs {
+			Inputs::CommitOnly(inputs) => {
+				writer.write_u8(0)?;
+				write_len(writer, inputs.len())?;
+				for i in inputs.iter() {
+					i.write(writer)?;
+				}
+			}
+			Inputs::FeaturesAndCommit(inputs) => {
+				writer.write_u8(1)?;
+				write_len(writer, inputs.len())?;
+				for i in inputs.iter() {
+					i.write(writer)?;
+				}
+			}
+		}
+		write_len(writer, self.outputs.len())?;
+		for o in self.outputs.iter() {
+			o.write(writer)?;
+		}
+		write_len(writer, self.kernels.len())?;
+		for k in self.kernels.iter() {
+			k.write(writer)?;
+		}
+		self.tko.write(writer)?;
+		match &self.proof_sig {
+			Some(p) => {
+				writer.write_u8(1)?;
+				writer.write_fixed_bytes(p.sender_address.to_bytes())?;
+				writer.write_fixed_bytes(p.receiver_address.to_bytes())?;
+				match p.receiver_signature {
+					Some(s) => {
+						writer.write_u8(1)?;
+						writer.write_fixed_bytes(&s.to_bytes().to_vec())?;
+					}
+					None => writer.write_u8(0)?,
+				}
+			}
+			None => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+/// Write a list length as u16, failing rather than truncating longer lists.
+fn write_len<W: Writer>(writer: &mut W, len: usize) -> Result<(), grin_ser::Error> {
+	if len > u16::MAX as usize {
+		return Err(grin_ser::Error::IOErr(
+			format!("too many items to serialize: {}", len),
+			std::io::ErrorKind::InvalidInput,
+		));
+	}
+	writer.write_u16(len as u16)
+}
+
+impl Readable for TransactionData {
+	fn read<R: Reader>(reader: &mut R) -> Result<TransactionData, grin_ser::Error> {
+		if reader.read_u8()? != DEVICE_TX_DATA_VERSION {
+			return Err(grin_ser::Error::UnsupportedProtocolVersion);
+		}
+		let inputs = match reader.read_u8()? {
+			0 => {
+				let len = reader.read_u16()?;
+				let mut inputs = vec![];
+				for _ in 0..len {
+					inputs.push(CommitWrapper::read(reader)?);
+				}
+				Inputs::CommitOnly(inputs)
+			}
+			1 => {
+				let len = reader.read_u16()?;
+				let mut inputs = vec![];
+				for _ in 0..len {
+					inputs.push(Input::read(reader)?);
+				}
+				Inputs::FeaturesAndCommit(inputs)
+			}
+			_ => return Err(grin_ser::Error::CorruptedData),
+		};
+		let outputs_len = reader.read_u16()?;
+		let mut outputs = vec![];
+		for _ in 0..outputs_len {
+			outputs.push(Output::read(reader)?);
+		}
+		let kernels_len = reader.read_u16()?;
+		let mut kernels = vec![];
+		for _ in 0..kernels_len {
+			kernels.push(TxKernel::read(reader)?);
+		}
+		let tko = BlindingFactor::read(reader)?;
+		let proof_sig = match reader.read_u8()? {
+			0 => None,
+			1 => {
+				let sender_address = DalekPublicKey::from_bytes(&reader.read_fixed_bytes(32)?)
+					.map_err(|_| grin_ser::Error::CorruptedData)?;
+				let receiver_address = DalekPublicKey::from_bytes(&reader.read_fixed_bytes(32)?)
+					.map_err(|_| grin_ser::Error::CorruptedData)?;
+				let receiver_signature = match reader.read_u8()? {
+					0 => None,
+					1 => Some(
+						DalekSignature::try_from(&reader.read_fixed_bytes(64)?[..])
+							.map_err(|_| grin_ser::Error::CorruptedData)?,
+					),
+					_ => return Err(grin_ser::Error::CorruptedData),
+				};
+				Some(PaymentInfo {
+					sender_address,
+					receiver_address,
+					receiver_signature,
+				})
+			}
+			_ => return Err(grin_ser::Error::CorruptedData),
+		};
+		Ok(TransactionData {
+			inputs,
+			outputs,
+			kernels,
+			tko,
+			proof_sig,
+		})
+	}
+}
+
 // todo: put somewhere else
 pub struct SenderInputParams {
 	//i_slot: i8,
@@ -74,3 +228,65 @@ pub struct TxMutual {
 	paymentProofSignature: Option<PaymentInfo>,
 }
 */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::grin_core::core::OutputFeatures;
+	use crate::grin_util::secp::pedersen::{Commitment, RangeProof};
+	use ed25519_dalek::SecretKey as DalekSecretKey;
+
+	#[test]
+	fn device_bytes_round_trip() {
+		let sender_key = DalekSecretKey::from_bytes(&[1; 32]).unwrap();
+		let receiver_key = DalekSecretKey::from_bytes(&[2; 32]).unwrap();
+		let data = TransactionData {
+			inputs: Inputs::FeaturesAndCommit(vec![Input::new(
+				OutputFeatures::Plain,
+				Commitment::from_vec(vec![8; 33]),
+			)]),
+			outputs: vec![Output::new(
+				OutputFeatures::Plain,
+				Commitment::from_vec(vec![9; 33]),
+				RangeProof::zero(),
+			)],
+			kernels: vec![TxKernel::empty()],
+			tko: BlindingFactor::from_slice(&[3; 32]),
+			proof_sig: Some(PaymentInfo {
+				sender_address: DalekPublicKey::from(&sender_key),
+				receiver_address: DalekPublicKey::from(&receiver_key),
+				receiver_signature: None,
+			}),
+		};
+
+		let bytes = data.to_device_bytes().unwrap();
+		assert_eq!(bytes[0], DEVICE_TX_DATA_VERSION);
+
+		let data2 = TransactionData::from_device_bytes(&bytes).unwrap();
+		assert_eq!(data2.inputs, data.inputs);
+		assert_eq!(data2.outputs, data.outputs);
+		assert_eq!(data2.kernels, data.kernels);
+		assert_eq!(data2.tko, data.tko);
+		let proof = data2.proof_sig.as_ref().unwrap();
+		assert_eq!(proof.sender_address, DalekPublicKey::from(&sender_key));
+		assert!(proof.receiver_signature.is_none());
+		assert_eq!(data2.to_device_bytes().unwrap(), bytes);
+
+		// Unknown version
+		let mut bad = bytes.clone();
+		bad[0] = 2;
+		assert!(TransactionData::from_device_bytes(&bad).is_err());
+
+		// Payment proof tag other than 0 or 1
+		let tag = bytes.len() - 1 - 64 - 1;
+		assert_eq!(bytes[tag], 1);
+		let mut bad = bytes.clone();
+		bad[tag] = 2;
+		assert!(TransactionData::from_device_bytes(&bad).is_err());
+
+		// Receiver signature tag other than 0 or 1
+		let mut bad = bytes.clone();
+		*bad.last_mut().unwrap() = 2;
+		assert!(TransactionData::from_device_bytes(&bad).is_err());
+	}
+}
@@ -14,21 +14,22 @@
 
 //! Keykeeper interface for Ledger hardware wallet.
 
-use crate::grin_keychain::{BlindSum, BlindingFactor, Keychain};
-use crate::hw::LedgerDevice;
+use crate::grin_core::core::amount_to_hr_string;
+use crate::grin_keychain::{BlindSum, BlindingFactor, Identifier, Keychain, SwitchCommitmentType};
+use crate::hw::{LedgerDevice, ReceiverResponse, SignerResponse};
 use crate::keykeeper_types::{KeyKeeper, SenderInputParams, TransactionData};
 use crate::slate::Slate;
 use crate::types::Context;
-use crate::Error;
+use crate::{Error, ErrorKind};
+use futures::executor::block_on;
 
 pub struct LedgerKeyKeeper {
 	ledger: LedgerDevice,
 }
 
 impl KeyKeeper for LedgerKeyKeeper {
-	fn get_num_slots(&mut self) -> Result<(), Error> {
-		let slotsCount = self.ledger.get_num_slots();
-		Ok(())
+	fn get_num_slots(&mut self) -> Result<usize, Error> {
+		Ok(block_on(self.ledger.get_num_slots())?)
 	}
 
 	fn get_rangeproof(&mut self) -> Result<(), Error> {
@@ -38,32 +39,32 @@ impl KeyKeeper for LedgerKeyKeeper {
 }
 
 impl LedgerKeyKeeper {
-	pub fn new() -> LedgerKeyKeeper {
-		LedgerKeyKeeper {
-			ledger: LedgerDevice::new(),
-		}
+	pub fn new() -> Result<LedgerKeyKeeper, Error> {
+		Ok(LedgerKeyKeeper {
+			ledger: LedgerDevice::new()?,
+		})
+	}
+
+	/// Use the given device, e.g. one with a mock transport.
+	pub fn with_device(ledger: LedgerDevice) -> LedgerKeyKeeper {
+		LedgerKeyKeeper { ledger }
 	}
 
 	// fee: from estimate_send_tx
 	pub fn sign_sender<K: Keychain>(
 		&mut self,
 		keychain: &K,
+		context: &Context,
 		slate: &Slate,
 		height: u64,
-	) -> Result<(), Error> {
-		let keychain = w.keychain(keychain_mask)?;
-
+	) -> Result<SignerResponse, Error> {
 		// Get inputs and outputs
 		let tx = slate.tx.as_ref().expect("Error getting transaction body.");
-		let tx_body = tx.body;
-		let inputs = tx_body.inputs;
-		let outputs = tx_body.outputs;
-		let kernels = tx_body.kernels;
 		let data = TransactionData {
-			inputs: inputs,
-			outputs: outputs,
-			kernels: kernels,
-			tko: None,
+			inputs: tx.body.inputs.clone(),
+			outputs: tx.body.outputs.clone(),
+			kernels: tx.body.kernels.clone(),
+			tko: tx.offset.clone(),
 			proof_sig: None,
 		};
 
@@ -79,29 +80,31 @@ impl LedgerKeyKeeper {
 		//let height = ;
 		let payment_proof = &slate.payment_proof;
 		let sender_input_params = SenderInputParams {};
-		self.ledger.sign_sender(keychain, data, sender_input_params);
-
-		Ok(())
+		// A decline on the device surfaces as ErrorKind::HardwareUserRejected
+		Ok(block_on(self.ledger.sign_sender(
+			keychain,
+			context,
+			data,
+			sender_input_params,
+		))?)
 	}
 
-	pub fn sign_receiver(&mut self, slate: &Slate) -> Result<(), Error> {
-		let keychain = w.keychain(keychain_mask)?;
-
+	pub fn sign_receiver<K: Keychain>(
+		&mut self,
+		keychain: &K,
+		context: &Context,
+		slate: &Slate,
+	) -> Result<ReceiverResponse, Error> {
 		let tx = slate.tx.as_ref().expect("Error getting transaction body.");
-		let tx_body = tx.body;
-		let inputs = tx_body.inputs;
-		let outputs = tx_body.outputs;
-		let kernels = tx_body.kernels;
 		let data = TransactionData {
-			inputs: inputs,
-			outputs: outputs,
-			kernels: kernels,
-			tko: None,
+			inputs: tx.body.inputs.clone(),
+			outputs: tx.body.outputs.clone(),
+			kernels: tx.body.kernels.clone(),
+			tko: tx.offset.clone(),
 			proof_sig: None,
 		};
-		self.ledger.sign_receiver(keychain, data);
-
-		Ok(())
+		// A decline on the device surfaces as ErrorKind::HardwareUserRejected
+		Ok(block_on(self.ledger.sign_receiver(keychain, context, data))?)
 	}
 
 	pub fn sign_finalize(&mut self, slate: &Slate) -> Result<(), Error> {
@@ -147,13 +150,23 @@ impl LedgerKeyKeeper {
 		self.ledger.get_payment_proof();
 	}
 
-	pub fn select_input(&mut self,) -> ()
-	{
-		let id = ;
-		let value = ;
-		let switch_commitment_type = ;
-		let data = ;
-		self.ledger.select_input();
+	/// Select the inputs on the device, after checking they cover the
+	/// outputs and fee.
+	pub fn select_input(
+		&mut self,
+		inputs: &[(Identifier, u64, SwitchCommitmentType)],
+		output_values: &[u64],
+		fee: u64,
+	) -> Result<(), Error> {
+		let input_values: Vec<u64> = inputs.iter().map(|(_, value, _)| *value).collect();
+		check_input_balance(&input_values, output_values, fee)?;
+		for (id, value, switch_commitment_type) in inputs {
+			block_on(
+				self.ledger
+					.select_input(id, *value, switch_commitment_type.clone()),
+			)?;
+		}
+		Ok(())
 	}
 
 	pub fn select_output(&mut self,) -> ()
@@ -213,3 +226,121 @@ impl LedgerKeyKeeper {
 	}
 
 }
+
+/// Verify the selected inputs sum to at least the outputs plus fee,
+/// so an under-funded selection fails before the device is engaged.
+pub fn check_input_balance(
+	input_values: &[u64],
+	output_values: &[u64],
+	fee: u64,
+) -> Result<(), Error> {
+	let overflow = || ErrorKind::GenericError("amount overflow".to_owned());
+	let available = input_values
+		.iter()
+		.try_fold(0u64, |acc, v| acc.checked_add(*v))
+		.ok_or_else(overflow)?;
+	let needed = output_values
+		.iter()
+		.try_fold(fee, |acc, v| acc.checked_add(*v))
+		.ok_or_else(overflow)?;
+	if available < needed {
+		return Err(ErrorKind::NotEnoughFunds {
+			available,
+			available_disp: amount_to_hr_string(available, false),
+			needed,
+			needed_disp: amount_to_hr_string(needed, false),
+		}
+		.into());
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::grin_keychain::ExtKeychain;
+	use crate::grin_util::static_secp_instance;
+	use crate::hw::apdu_types::APDUTransport;
+	use crate::hw::ledger_error::LedgerHIDError;
+	use crate::hw::mock::MockExchange;
+
+	#[test]
+	fn input_balance_check() {
+		assert!(check_input_balance(&[60, 50], &[100], 10).is_ok());
+		assert!(check_input_balance(&[200], &[100, 50], 10).is_ok());
+
+		match check_input_balance(&[60, 40], &[100], 10) {
+			Err(e) => assert_eq!(
+				e.kind(),
+				ErrorKind::NotEnoughFunds {
+					available: 100,
+					available_disp: amount_to_hr_string(100, false),
+					needed: 110,
+					needed_disp: amount_to_hr_string(110, false),
+				}
+			),
+			Ok(_) => panic!("under-funded inputs accepted"),
+		}
+		assert!(check_input_balance(&[], &[1], 0).is_err());
+		assert!(check_input_balance(&[u64::MAX, 1], &[1], 0).is_err());
+	}
+
+	#[test]
+	fn missing_device_error() {
+		let e: Error = LedgerHIDError::DeviceNotFound.into();
+		assert_eq!(e.kind(), ErrorKind::HardwareNotFound);
+
+		let e: Error = LedgerHIDError::Comm("Invalid sequence idx").into();
+		match e.kind() {
+			ErrorKind::HardwareWallet(msg) => assert!(msg.contains("Invalid sequence idx")),
+			k => panic!("unexpected error kind {:?}", k),
+		}
+	}
+
+	#[test]
+	fn sign_receiver_user_rejected() {
+		let keychain = ExtKeychain::from_random_seed(true).unwrap();
+		let context = {
+			let secp = static_secp_instance();
+			let secp = secp.lock();
+			Context::new(&secp, &ExtKeychain::derive_key_id(2, 0, 0, 0, 0), true, false)
+		};
+		let mut slate = Slate::blank(2, false);
+		slate.tx = Some(Slate::empty_transaction());
+
+		// Free slots, then a decline on the device
+		let mock = MockExchange::new(vec![(b"0001".to_vec(), 0x9000), (vec![], 0x6985)]);
+		let ledger = LedgerDevice::with_transport(APDUTransport::new(mock));
+		let mut keykeeper = LedgerKeyKeeper::with_device(ledger);
+		match keykeeper.sign_receiver(&keychain, &context, &slate) {
+			Err(e) => assert_eq!(e.kind(), ErrorKind::HardwareUserRejected),
+			Ok(_) => panic!("decline on device not reported"),
+		}
+	}
+
+	#[test]
+	fn select_input_device_error() {
+		let id = ExtKeychain::derive_key_id(2, 1, 0, 0, 0);
+		let inputs = vec![(id, 100, SwitchCommitmentType::Regular)];
+
+		let mock = MockExchange::new(vec![(vec![], 0x9000), (vec![], 0x6A80)]);
+		let commands = mock.commands();
+		let ledger = LedgerDevice::with_transport(APDUTransport::new(mock));
+		let mut keykeeper = LedgerKeyKeeper::with_device(ledger);
+		assert!(keykeeper.select_input(&inputs, &[90], 10).is_ok());
+		assert_eq!(commands.lock().unwrap().len(), 1);
+
+		// The device rejecting the input is reported
+		match keykeeper.select_input(&inputs, &[90], 10) {
+			Err(e) => match e.kind() {
+				ErrorKind::HardwareWallet(_) => (),
+				k => panic!("unexpected error kind {:?}", k),
+			},
+			Ok(_) => panic!("rejected input accepted"),
+		}
+
+		// Under-funded inputs never reach the device
+		assert!(keykeeper.select_input(&inputs, &[100], 10).is_err());
+		assert_eq!(commands.lock().unwrap().len(), 2);
+	}
+}